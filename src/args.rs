@@ -0,0 +1,48 @@
+use std::env;
+use std::path::PathBuf;
+
+pub struct Args {
+    pub config: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    pub record: Option<PathBuf>,
+    pub level: Option<PathBuf>,
+    // The level file to open in the editor instead of starting a game.
+    pub edit: Option<PathBuf>,
+    // Any other `--some-setting value` pair, to be applied on top of the
+    // config file as `some_setting = value`.
+    pub overrides: Vec<(String, String)>,
+}
+
+impl Args {
+    pub fn parse() -> Result<Args, String> {
+        let mut args = Args {
+            config: None,
+            replay: None,
+            record: None,
+            level: None,
+            edit: None,
+            overrides: Vec::new(),
+        };
+
+        let mut iter = env::args().skip(1);
+        while let Some(arg) = iter.next() {
+            let name = arg.strip_prefix("--")
+                .ok_or_else(|| format!("unknown argument: {}", arg))?;
+            let value = iter.next().ok_or_else(|| format!("{} needs a value", arg))?;
+            match name {
+                "config" => args.config = Some(PathBuf::from(value)),
+                "replay" => args.replay = Some(PathBuf::from(value)),
+                "record" => args.record = Some(PathBuf::from(value)),
+                "level"  => args.level = Some(PathBuf::from(value)),
+                "edit"   => args.edit = Some(PathBuf::from(value)),
+                _ => args.overrides.push((name.replace('-', "_"), value)),
+            }
+        }
+
+        if args.edit.is_some() && (args.level.is_some() || args.replay.is_some()) {
+            return Err("--edit cannot be combined with --level or --replay".to_string());
+        }
+
+        Ok(args)
+    }
+}
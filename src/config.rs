@@ -0,0 +1,567 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::level::Level;
+
+pub const DEFAULT_CONFIG_FILE: &str = "snektris.cfg";
+pub const MAX_BOARD_SIZE: usize = 100;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Invalid { line: usize, reason: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(error) =>
+                write!(f, "could not read config: {}", error),
+            ConfigError::Invalid { line, reason } =>
+                write!(f, "invalid config on line {}: {}", line, reason),
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(error: io::Error) -> ConfigError {
+        ConfigError::Io(error)
+    }
+}
+
+// The point falling blocks rotate around.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RotationPivot {
+    // The cell that was the snake's head.
+    Head,
+    // The middle of the shape, rounded to the nearest cell corner or centre.
+    Centroid,
+}
+
+impl FromStr for RotationPivot {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<RotationPivot, ()> {
+        match s {
+            "head"     => Ok(RotationPivot::Head),
+            "centroid" => Ok(RotationPivot::Centroid),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for RotationPivot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            RotationPivot::Head     => "head",
+            RotationPivot::Centroid => "centroid",
+        })
+    }
+}
+
+// What happens to the blocks above a cleared row.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Gravity {
+    // Everything above moves down by one row.
+    Naive,
+    // Each connected lump of blocks falls until it lands on something.
+    Cascade,
+}
+
+impl FromStr for Gravity {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Gravity, ()> {
+        match s {
+            "naive"   => Ok(Gravity::Naive),
+            "cascade" => Ok(Gravity::Cascade),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Gravity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Gravity::Naive   => "naive",
+            Gravity::Cascade => "cascade",
+        })
+    }
+}
+
+// How the snake's body falls once it has turned into blocks.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DropMode {
+    // The whole body falls as one piece and stops as soon as any of it lands.
+    Rigid,
+    // Each column of the body falls on its own.
+    Columns,
+    // Each segment falls on its own.
+    Segments,
+}
+
+impl FromStr for DropMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<DropMode, ()> {
+        match s {
+            "rigid"    => Ok(DropMode::Rigid),
+            "columns"  => Ok(DropMode::Columns),
+            "segments" => Ok(DropMode::Segments),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for DropMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            DropMode::Rigid    => "rigid",
+            DropMode::Columns  => "columns",
+            DropMode::Segments => "segments",
+        })
+    }
+}
+
+// Where new fruits are put, out of the free cells the snake can reach.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FruitSpawn {
+    // Any of them, all equally likely.
+    Uniform,
+    // Preferably ones resting on the stack or the floor.
+    NearStack,
+    // One of the farthest from the snake's head.
+    FarFromHead,
+}
+
+impl FromStr for FruitSpawn {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<FruitSpawn, ()> {
+        match s {
+            "uniform"       => Ok(FruitSpawn::Uniform),
+            "near-stack"    => Ok(FruitSpawn::NearStack),
+            "far-from-head" => Ok(FruitSpawn::FarFromHead),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for FruitSpawn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            FruitSpawn::Uniform     => "uniform",
+            FruitSpawn::NearStack   => "near-stack",
+            FruitSpawn::FarFromHead => "far-from-head",
+        })
+    }
+}
+
+// When eaten fruits are replaced.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FruitRespawn {
+    // As soon as the snake eats one.
+    OnEat,
+    // Once the snake it was eaten by has landed.
+    OnLanding,
+}
+
+impl FromStr for FruitRespawn {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<FruitRespawn, ()> {
+        match s {
+            "on-eat"     => Ok(FruitRespawn::OnEat),
+            "on-landing" => Ok(FruitRespawn::OnLanding),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for FruitRespawn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            FruitRespawn::OnEat     => "on-eat",
+            FruitRespawn::OnLanding => "on-landing",
+        })
+    }
+}
+
+// What decides the color a landed snake's blocks are drawn in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlockColors {
+    // How many snakes landed before it, cycling through the palette.
+    Generation,
+    // How long it was.
+    Length,
+    // The kind of fruit it ate.
+    Fruit,
+}
+
+impl FromStr for BlockColors {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<BlockColors, ()> {
+        match s {
+            "generation" => Ok(BlockColors::Generation),
+            "length"     => Ok(BlockColors::Length),
+            "fruit"      => Ok(BlockColors::Fruit),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for BlockColors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            BlockColors::Generation => "generation",
+            BlockColors::Length     => "length",
+            BlockColors::Fruit      => "fruit",
+        })
+    }
+}
+
+// What gets cleared once blocks land.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ruleset {
+    // Full rows.
+    Rows,
+    // Full rows, and groups of `match_size` or more same-colored blocks.
+    ColorMatch,
+}
+
+impl FromStr for Ruleset {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Ruleset, ()> {
+        match s {
+            "rows"        => Ok(Ruleset::Rows),
+            "color-match" => Ok(Ruleset::ColorMatch),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Ruleset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Ruleset::Rows       => "rows",
+            Ruleset::ColorMatch => "color-match",
+        })
+    }
+}
+
+// Which edges of the board the snake can pass through to come out on the
+// other side. Falling blocks always stop at the walls.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Wrap {
+    Off,
+    Horizontal,
+    Both,
+}
+
+impl Wrap {
+    pub fn horizontal(self) -> bool {
+        self != Wrap::Off
+    }
+
+    pub fn vertical(self) -> bool {
+        self == Wrap::Both
+    }
+}
+
+impl FromStr for Wrap {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Wrap, ()> {
+        match s {
+            "off"        => Ok(Wrap::Off),
+            "horizontal" => Ok(Wrap::Horizontal),
+            "both"       => Ok(Wrap::Both),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Wrap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Wrap::Off        => "off",
+            Wrap::Horizontal => "horizontal",
+            Wrap::Both       => "both",
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct GameConfig {
+    pub map_width: usize,
+    pub map_height: usize,
+    // Milliseconds between snake moves and between falling block moves on
+    // the first level. Both shrink by `speed_curve` with every level, down
+    // to `min_speed`.
+    pub speed: u64,
+    pub fall_speed: u64,
+    pub speed_curve: f64,
+    pub min_speed: u64,
+    pub lines_per_level: u32,
+    // Rows to clear to win; 0 plays on until the game is lost.
+    pub goal: u32,
+    pub rotation_pivot: RotationPivot,
+    pub gravity: Gravity,
+    pub ruleset: Ruleset,
+    pub match_size: usize,
+    pub drop_mode: DropMode,
+    pub wrap: Wrap,
+    // What dropping the snake without eating a fruit costs: points, the
+    // milliseconds before it can be done again, and snake segments.
+    pub detach_cost: u64,
+    pub detach_cooldown: u64,
+    pub detach_length_loss: usize,
+    // Free cells a new snake needs in front of it to be allowed to spawn.
+    pub spawn_runway: usize,
+    pub fruit_spawn: FruitSpawn,
+    // How many fruits are on the board at once.
+    pub fruit_count: usize,
+    pub fruit_respawn: FruitRespawn,
+    // How likely each kind of fruit is, relative to the others.
+    pub fruit_weight_normal: u32,
+    pub fruit_weight_golden: u32,
+    pub fruit_weight_shrinking: u32,
+    pub fruit_weight_bomb: u32,
+    pub fruit_weight_slow_motion: u32,
+    // Show where the snake would land if it ate a fruit now.
+    pub landing_preview: bool,
+    pub block_colors: BlockColors,
+    pub window_width: f32,
+    pub window_height: f32,
+    pub seed: Option<u64>,
+    // The board to start from, if not an empty one.
+    pub level: Option<Level>,
+}
+
+impl Default for GameConfig {
+    fn default() -> GameConfig {
+        GameConfig {
+            map_width: 9,
+            map_height: 9,
+            speed: 333,
+            fall_speed: 111,
+            speed_curve: 0.85,
+            min_speed: 33,
+            lines_per_level: 10,
+            goal: 0,
+            rotation_pivot: RotationPivot::Head,
+            gravity: Gravity::Naive,
+            ruleset: Ruleset::Rows,
+            match_size: 4,
+            drop_mode: DropMode::Rigid,
+            wrap: Wrap::Off,
+            detach_cost: 25,
+            detach_cooldown: 0,
+            detach_length_loss: 0,
+            spawn_runway: 3,
+            fruit_spawn: FruitSpawn::Uniform,
+            fruit_count: 1,
+            fruit_respawn: FruitRespawn::OnLanding,
+            fruit_weight_normal: 20,
+            fruit_weight_golden: 2,
+            fruit_weight_shrinking: 3,
+            fruit_weight_bomb: 2,
+            fruit_weight_slow_motion: 3,
+            landing_preview: false,
+            block_colors: BlockColors::Generation,
+            window_width: 480.0,
+            window_height: 480.0,
+            seed: None,
+            level: None,
+        }
+    }
+}
+
+impl GameConfig {
+    // Reads `key = value` lines on top of the defaults. Blank lines and
+    // lines starting with `#` are ignored.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<GameConfig, ConfigError> {
+        let contents = fs::read_to_string(path)?;
+        let mut config = GameConfig::default();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |reason: String| ConfigError::Invalid { line: i + 1, reason };
+            let (key, value) = line.split_once('=')
+                .ok_or_else(|| invalid(format!("expected key = value, got {}", line)))?;
+            config.set(key.trim(), value.trim()).map_err(invalid)?;
+        }
+        Ok(config)
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "map_width"       => self.map_width = parse(key, value)?,
+            "map_height"      => self.map_height = parse(key, value)?,
+            "speed"           => self.speed = parse(key, value)?,
+            "fall_speed"      => self.fall_speed = parse(key, value)?,
+            "speed_curve"     => self.speed_curve = parse(key, value)?,
+            "min_speed"       => self.min_speed = parse(key, value)?,
+            "lines_per_level" => self.lines_per_level = parse(key, value)?,
+            "goal"            => self.goal = parse(key, value)?,
+            "rotation_pivot"  => self.rotation_pivot = parse(key, value)?,
+            "gravity"         => self.gravity = parse(key, value)?,
+            "ruleset"         => self.ruleset = parse(key, value)?,
+            "match_size"      => self.match_size = parse(key, value)?,
+            "drop_mode"       => self.drop_mode = parse(key, value)?,
+            "wrap"            => self.wrap = parse(key, value)?,
+            "detach_cost"     => self.detach_cost = parse(key, value)?,
+            "detach_cooldown" => self.detach_cooldown = parse(key, value)?,
+            "detach_length_loss" => self.detach_length_loss = parse(key, value)?,
+            "spawn_runway"    => self.spawn_runway = parse(key, value)?,
+            "fruit_spawn"     => self.fruit_spawn = parse(key, value)?,
+            "fruit_count"     => self.fruit_count = parse(key, value)?,
+            "fruit_respawn"   => self.fruit_respawn = parse(key, value)?,
+            "fruit_weight_normal"      => self.fruit_weight_normal = parse(key, value)?,
+            "fruit_weight_golden"      => self.fruit_weight_golden = parse(key, value)?,
+            "fruit_weight_shrinking"   => self.fruit_weight_shrinking = parse(key, value)?,
+            "fruit_weight_bomb"        => self.fruit_weight_bomb = parse(key, value)?,
+            "fruit_weight_slow_motion" => self.fruit_weight_slow_motion = parse(key, value)?,
+            "landing_preview" => self.landing_preview = parse(key, value)?,
+            "block_colors"    => self.block_colors = parse(key, value)?,
+            "window_width"    => self.window_width = parse(key, value)?,
+            "window_height"   => self.window_height = parse(key, value)?,
+            "seed"            => self.seed = Some(parse(key, value)?),
+            _ => return Err(format!("unknown setting: {}", key)),
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.map_width < 5 || self.map_height < 4 {
+            return Err(format!("board must be at least 5x4, got {}x{}",
+                               self.map_width, self.map_height));
+        }
+        if self.map_width > MAX_BOARD_SIZE || self.map_height > MAX_BOARD_SIZE {
+            return Err(format!("board must be at most {}x{}, got {}x{}",
+                               MAX_BOARD_SIZE, MAX_BOARD_SIZE, self.map_width, self.map_height));
+        }
+        if let Some(level) = &self.level {
+            if (level.width, level.height) != (self.map_width, self.map_height) {
+                return Err(format!("the level is {}x{}, the board was set to {}x{}",
+                                   level.width, level.height, self.map_width, self.map_height));
+            }
+        }
+        if self.spawn_runway >= self.map_width.max(self.map_height) {
+            return Err(format!("spawn_runway must be shorter than the board is wide or high, got {}",
+                               self.spawn_runway));
+        }
+        if self.speed == 0 || self.fall_speed == 0 || self.min_speed == 0 {
+            return Err("speeds must be above zero".to_string());
+        }
+        if !(self.speed_curve > 0.0 && self.speed_curve <= 1.0) {
+            return Err(format!("speed_curve must be above 0 and at most 1, got {}",
+                               self.speed_curve));
+        }
+        if self.lines_per_level == 0 {
+            return Err("lines_per_level must be above zero".to_string());
+        }
+        if self.match_size < 2 {
+            return Err(format!("match_size must be at least 2, got {}", self.match_size));
+        }
+        if self.fruit_count == 0 || self.fruit_count >= self.map_width * self.map_height {
+            return Err(format!("fruit_count must be above zero and leave room on the board, got {}",
+                               self.fruit_count));
+        }
+        let weights = [
+            self.fruit_weight_normal,
+            self.fruit_weight_golden,
+            self.fruit_weight_shrinking,
+            self.fruit_weight_bomb,
+            self.fruit_weight_slow_motion,
+        ];
+        let total: u64 = weights.iter().map(|weight| *weight as u64).sum();
+        if total == 0 {
+            return Err("at least one fruit weight must be above zero".to_string());
+        }
+        if total > u32::MAX as u64 {
+            return Err(format!("fruit weights must add up to at most {}, got {}",
+                               u32::MAX, total));
+        }
+        if self.window_width <= 0.0 || self.window_height <= 0.0 {
+            return Err("window size must be above zero".to_string());
+        }
+        Ok(())
+    }
+
+    // The settings besides the board size that change how a game plays
+    // out, and so have to match for a replay to play back the same.
+    pub fn rules(&self) -> Vec<(&'static str, String)> {
+        vec!(
+            ("speed", self.speed.to_string()),
+            ("fall_speed", self.fall_speed.to_string()),
+            ("speed_curve", self.speed_curve.to_string()),
+            ("min_speed", self.min_speed.to_string()),
+            ("lines_per_level", self.lines_per_level.to_string()),
+            ("goal", self.goal.to_string()),
+            ("level", match &self.level {
+                Some(level) => format!("{:016x}", level.checksum()),
+                None => "none".to_string(),
+            }),
+            ("rotation_pivot", self.rotation_pivot.to_string()),
+            ("gravity", self.gravity.to_string()),
+            ("ruleset", self.ruleset.to_string()),
+            ("match_size", self.match_size.to_string()),
+            ("drop_mode", self.drop_mode.to_string()),
+            ("wrap", self.wrap.to_string()),
+            ("detach_cost", self.detach_cost.to_string()),
+            ("detach_cooldown", self.detach_cooldown.to_string()),
+            ("detach_length_loss", self.detach_length_loss.to_string()),
+            ("spawn_runway", self.spawn_runway.to_string()),
+            ("fruit_spawn", self.fruit_spawn.to_string()),
+            ("fruit_count", self.fruit_count.to_string()),
+            ("fruit_respawn", self.fruit_respawn.to_string()),
+            ("fruit_weight_normal", self.fruit_weight_normal.to_string()),
+            ("fruit_weight_golden", self.fruit_weight_golden.to_string()),
+            ("fruit_weight_shrinking", self.fruit_weight_shrinking.to_string()),
+            ("fruit_weight_bomb", self.fruit_weight_bomb.to_string()),
+            ("fruit_weight_slow_motion", self.fruit_weight_slow_motion.to_string()),
+            ("block_colors", self.block_colors.to_string()),
+        )
+    }
+}
+
+fn parse<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid value for {}: {}", key, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boards_past_the_maximum_are_rejected() {
+        let config = GameConfig {
+            map_width: MAX_BOARD_SIZE,
+            map_height: MAX_BOARD_SIZE,
+            ..GameConfig::default()
+        };
+        assert!(config.validate().is_ok());
+        let config = GameConfig { map_width: usize::MAX, map_height: usize::MAX, ..config };
+        assert!(config.validate().is_err());
+        let config = GameConfig { map_width: 9, map_height: MAX_BOARD_SIZE + 1, ..config };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn runways_that_cannot_fit_the_board_are_rejected() {
+        let config = GameConfig { spawn_runway: 8, ..GameConfig::default() };
+        assert!(config.validate().is_ok());
+        let config = GameConfig { spawn_runway: 9, ..config };
+        assert!(config.validate().is_err());
+        let config = GameConfig { spawn_runway: 1 << 32, ..config };
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,187 @@
+use std::path::PathBuf;
+
+use tetra::graphics::{self, Color, Texture};
+use tetra::graphics::text::{Font, Text};
+use tetra::input::{self, Key, MouseButton};
+use tetra::math::Vec2;
+use tetra::Context;
+
+use snektris::config::GameConfig;
+use snektris::level::{Level, Tile};
+use snektris::simulation::Direction;
+
+// The board can be made bigger than this, but it gets hard to paint.
+const MAX_SIZE: usize = 40;
+
+// Paints a level with the mouse and saves it to the file it was opened
+// from, which does not have to exist yet.
+pub struct Editor {
+    level: Level,
+    path: PathBuf,
+    // The settings a play-test starts from, before the level's own.
+    config: GameConfig,
+    tool: Tile,
+    message: String,
+    texture: Texture,
+    font: Font,
+}
+
+impl Editor {
+    pub fn new(ctx: &mut Context, config: GameConfig, path: PathBuf) -> tetra::Result<Editor> {
+        let texture = Texture::new(ctx, "./snake.png")?;
+        let font = Font::vector(ctx, "./DejaVuSansMono.ttf", 16.0)?;
+        let name = path.file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut editor = Editor {
+            level: Level::new(&name, config.map_width, config.map_height),
+            path,
+            config,
+            tool: Tile::Block,
+            message: String::new(),
+            texture,
+            font,
+        };
+        if editor.path.exists() {
+            editor.load();
+        } else {
+            editor.message = format!("New level, S saves it to {}", editor.path.display());
+        }
+        Ok(editor)
+    }
+
+    fn load(&mut self) {
+        match Level::load(&self.path) {
+            Ok(level) => {
+                self.level = level;
+                self.message = format!("Loaded {}", self.path.display());
+            }
+            Err(error) => self.message = format!("{}: {}", self.path.display(), error),
+        }
+    }
+
+    fn save(&mut self) {
+        self.message = match self.level.save(&self.path) {
+            Ok(()) => format!("Saved {}", self.path.display()),
+            Err(error) => format!("could not save {}: {}", self.path.display(), error),
+        };
+    }
+
+    // The config to play the level with, as long as the level and the
+    // settings in it make a valid game.
+    fn play_test_config(&self) -> Result<GameConfig, String> {
+        let mut config = self.config.clone();
+        self.level.clone().apply(&mut config).map_err(|error| error.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn part_size(&self) -> f32 {
+        f32::min(self.config.window_width / self.level.width as f32,
+                 self.config.window_height / self.level.height as f32)
+    }
+
+    fn hovered_cell(&self, ctx: &Context) -> Option<Vec2<usize>> {
+        let position = input::get_mouse_position(ctx) / self.part_size();
+        if position.x < 0.0 || position.y < 0.0 {
+            return None;
+        }
+        let cell = Vec2::new(position.x as usize, position.y as usize);
+        if cell.x < self.level.width && cell.y < self.level.height {
+            Some(cell)
+        } else {
+            None
+        }
+    }
+
+    // Returns the config to play-test the level with once the player asks
+    // for it.
+    pub fn update(&mut self, ctx: &mut Context) -> Option<GameConfig> {
+        for key in input::get_keys_pressed(ctx).copied().collect::<Vec<_>>() {
+            let (width, height) = (self.level.width, self.level.height);
+            match key {
+                Key::Num1  => self.tool = Tile::Block,
+                Key::Num2  => self.tool = Tile::Wall,
+                Key::Num3  => self.tool = Tile::Fruit,
+                Key::Num4  => self.tool = Tile::Snake(self.level.snake.1),
+                Key::Left  => self.level.resize(width.saturating_sub(1).max(5), height),
+                Key::Right => self.level.resize((width + 1).min(MAX_SIZE), height),
+                Key::Up    => self.level.resize(width, height.saturating_sub(1).max(4)),
+                Key::Down  => self.level.resize(width, (height + 1).min(MAX_SIZE)),
+                Key::S     => self.save(),
+                Key::L     => self.load(),
+                Key::P     => match self.play_test_config() {
+                    Ok(config) => return Some(config),
+                    Err(error) => self.message = error,
+                },
+                _ => {}
+            }
+        }
+
+        if let Some(cell) = self.hovered_cell(ctx) {
+            if input::is_mouse_button_pressed(ctx, MouseButton::Left) &&
+               matches!(self.tool, Tile::Snake(_)) && self.level.snake.0 == cell {
+                // Clicking the snake start again turns it.
+                let direction = match self.level.snake.1 {
+                    Direction::Up    => Direction::Right,
+                    Direction::Right => Direction::Down,
+                    Direction::Down  => Direction::Left,
+                    Direction::Left  => Direction::Up,
+                };
+                self.tool = Tile::Snake(direction);
+                self.level.set_tile(cell, self.tool);
+            } else if input::is_mouse_button_down(ctx, MouseButton::Left) {
+                self.level.set_tile(cell, self.tool);
+            } else if input::is_mouse_button_down(ctx, MouseButton::Right) {
+                self.level.set_tile(cell, Tile::Empty);
+            }
+        }
+        None
+    }
+
+    pub fn draw(&mut self, ctx: &mut Context) {
+        graphics::clear(ctx, Color::rgb(0.5, 0.5, 1.0));
+
+        let part_size = self.part_size();
+        let scale = Vec2::new(part_size / self.texture.width()  as f32,
+                              part_size / self.texture.height() as f32);
+        for y in 0..self.level.height {
+            for x in 0..self.level.width {
+                let cell = Vec2::new(x, y);
+                let tile = self.level.tile(cell);
+                let color = match tile {
+                    Tile::Empty    => Color::rgba(1.0, 1.0, 1.0, 0.1),
+                    Tile::Block    => Color::rgb(0.5, 0.5, 0.5),
+                    Tile::Wall     => Color::rgb(0.25, 0.25, 0.3),
+                    Tile::Fruit    => Color::rgb(1.0, 0.5, 0.5),
+                    Tile::Snake(_) => Color::rgb(0.5, 1.0, 0.5),
+                };
+                let position = Vec2::new(x as f32 * part_size, y as f32 * part_size);
+                let params = graphics::DrawParams::new()
+                    .position(position)
+                    .scale(scale)
+                    .color(color);
+                self.texture.draw(ctx, params);
+                if let Tile::Snake(_) = tile {
+                    let mut text = Text::new(tile.to_char().to_string(), self.font.clone());
+                    text.draw(ctx, position + Vec2::new(part_size / 3.0, part_size / 4.0));
+                }
+            }
+        }
+
+        let tool = match self.tool {
+            Tile::Block    => "block",
+            Tile::Wall     => "wall",
+            Tile::Fruit    => "fruit",
+            Tile::Snake(_) => "snake start",
+            Tile::Empty    => "erase",
+        };
+        let mut text = Text::new(format!("{}  {}x{}  Painting: {}\n\
+                                          1-4 tool  Arrows resize  S save  L load  P play-test\n\
+                                          {}",
+                                         self.level.name, self.level.width, self.level.height,
+                                         tool, self.message),
+                                 self.font.clone());
+        text.draw(ctx, Vec2::new(4.0, 4.0));
+    }
+}
@@ -0,0 +1,154 @@
+use std::cmp::Reverse;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const MAX_ENTRIES: usize = 10;
+pub const MAX_NAME_LENGTH: usize = 12;
+
+#[derive(Clone, Debug)]
+pub struct HighScore {
+    pub name: String,
+    pub points: u64,
+    pub lines: u32,
+    pub fruits: u32,
+    pub max_length: usize,
+    pub seed: u64,
+    pub width: usize,
+    pub height: usize,
+    // Seconds since the Unix epoch.
+    pub date: u64,
+}
+
+impl HighScore {
+    pub fn date_string(&self) -> String {
+        let (year, month, day) = civil_from_days((self.date / 86400) as i64);
+        format!("{:04}-{:02}-{:02}", year, month, day)
+    }
+
+    fn to_line(&self) -> String {
+        format!("{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                self.name, self.points, self.lines, self.fruits, self.max_length,
+                self.seed, self.width, self.height, self.date)
+    }
+
+    fn from_line(line: &str) -> Option<HighScore> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 9 {
+            return None;
+        }
+        Some(HighScore {
+            name: fields[0].to_string(),
+            points: fields[1].parse().ok()?,
+            lines: fields[2].parse().ok()?,
+            fruits: fields[3].parse().ok()?,
+            max_length: fields[4].parse().ok()?,
+            seed: fields[5].parse().ok()?,
+            width: fields[6].parse().ok()?,
+            height: fields[7].parse().ok()?,
+            date: fields[8].parse().ok()?,
+        })
+    }
+}
+
+// The best MAX_ENTRIES games, best first, kept in a file in the user's
+// data directory.
+pub struct HighScores {
+    pub entries: Vec<HighScore>,
+}
+
+impl HighScores {
+    // A missing file just means nobody has played yet. Lines that fail to
+    // parse are skipped rather than throwing the whole table away.
+    pub fn load() -> io::Result<HighScores> {
+        let contents = match fs::read_to_string(file_path()) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(error) => return Err(error),
+        };
+        let mut entries: Vec<HighScore> = contents.lines()
+            .filter_map(HighScore::from_line)
+            .collect();
+        entries.sort_by_key(|entry| Reverse(entry.points));
+        entries.truncate(MAX_ENTRIES);
+        Ok(HighScores { entries })
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = file_path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut out = String::new();
+        for entry in self.entries.iter() {
+            out += &entry.to_line();
+            out.push('\n');
+        }
+        fs::write(path, out)
+    }
+
+    pub fn qualifies(&self, points: u64) -> bool {
+        points > 0 && (self.entries.len() < MAX_ENTRIES ||
+                       self.entries.iter().any(|entry| points > entry.points))
+    }
+
+    // Returns the rank the entry ended up at.
+    pub fn insert(&mut self, mut entry: HighScore) -> usize {
+        entry.name = entry.name.chars()
+            .filter(|c| !c.is_control())
+            .take(MAX_NAME_LENGTH)
+            .collect();
+        let rank = self.entries.iter()
+            .position(|other| entry.points > other.points)
+            .unwrap_or(self.entries.len());
+        self.entries.insert(rank, entry);
+        self.entries.truncate(MAX_ENTRIES);
+        rank
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+fn file_path() -> PathBuf {
+    data_dir().join("snektris").join("highscores.txt")
+}
+
+fn data_dir() -> PathBuf {
+    if cfg!(windows) {
+        if let Some(dir) = env::var_os("APPDATA") {
+            return PathBuf::from(dir);
+        }
+    } else if cfg!(target_os = "macos") {
+        if let Some(home) = env::var_os("HOME") {
+            return PathBuf::from(home).join("Library").join("Application Support");
+        }
+    } else {
+        if let Some(dir) = env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()) {
+            return PathBuf::from(dir);
+        }
+        if let Some(home) = env::var_os("HOME") {
+            return PathBuf::from(home).join(".local").join("share");
+        }
+    }
+    PathBuf::from(".")
+}
+
+// Howard Hinnant's days-to-civil-date algorithm.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
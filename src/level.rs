@@ -0,0 +1,300 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use vek::Vec2;
+
+use crate::config::GameConfig;
+use crate::simulation::Direction;
+
+#[derive(Debug)]
+pub enum LevelError {
+    Io(io::Error),
+    Invalid { line: usize, reason: String },
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LevelError::Io(error) =>
+                write!(f, "could not read level: {}", error),
+            LevelError::Invalid { line, reason } =>
+                write!(f, "invalid level on line {}: {}", line, reason),
+        }
+    }
+}
+
+impl From<io::Error> for LevelError {
+    fn from(error: io::Error) -> LevelError {
+        LevelError::Io(error)
+    }
+}
+
+// What a cell of a level holds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tile {
+    Empty,
+    Block,
+    Wall,
+    Fruit,
+    Snake(Direction),
+}
+
+impl Tile {
+    fn from_char(c: char) -> Option<Tile> {
+        match c {
+            '.' => Some(Tile::Empty),
+            '#' => Some(Tile::Block),
+            'X' => Some(Tile::Wall),
+            '*' => Some(Tile::Fruit),
+            '^' => Some(Tile::Snake(Direction::Up)),
+            'v' => Some(Tile::Snake(Direction::Down)),
+            '<' => Some(Tile::Snake(Direction::Left)),
+            '>' => Some(Tile::Snake(Direction::Right)),
+            _ => None,
+        }
+    }
+
+    pub fn to_char(self) -> char {
+        match self {
+            Tile::Empty => '.',
+            Tile::Block => '#',
+            Tile::Wall  => 'X',
+            Tile::Fruit => '*',
+            Tile::Snake(Direction::Up)    => '^',
+            Tile::Snake(Direction::Down)  => 'v',
+            Tile::Snake(Direction::Left)  => '<',
+            Tile::Snake(Direction::Right) => '>',
+        }
+    }
+}
+
+// A board to start from instead of an empty one. The file has `key = value`
+// lines like the config, then a line saying `map` and the board itself, one
+// character per cell:
+//
+//     .          empty
+//     #          a block of the stack
+//     X          a wall, which row clears and bombs leave alone
+//     *          a fruit
+//     ^ v < >    where the snake starts and which way it heads
+//
+// `name` and `size` (`<width>x<height>`, which has to match the map) are
+// about the level itself; any other key is a config setting, such as
+// `goal` or `speed`.
+#[derive(Clone, Debug)]
+pub struct Level {
+    pub name: String,
+    pub width: usize,
+    pub height: usize,
+    pub blocks: Vec<Vec2<usize>>,
+    pub walls: Vec<Vec2<usize>>,
+    pub fruits: Vec<Vec2<usize>>,
+    pub snake: (Vec2<usize>, Direction),
+    // The config settings, with the line each came from.
+    settings: Vec<(usize, String, String)>,
+}
+
+impl Level {
+    // An empty board with the snake starting at the top, heading down.
+    pub fn new(name: &str, width: usize, height: usize) -> Level {
+        Level {
+            name: name.to_string(),
+            width,
+            height,
+            blocks: Vec::new(),
+            walls: Vec::new(),
+            fruits: Vec::new(),
+            snake: (Vec2::new(width / 2, 0), Direction::Down),
+            settings: Vec::new(),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Level, LevelError> {
+        let contents = fs::read_to_string(path)?;
+        let mut level = Level::new("", 0, 0);
+        let mut size = None;
+        let mut snake = None;
+        let mut lines = contents.lines().enumerate();
+
+        for (i, line) in lines.by_ref() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line == "map" {
+                break;
+            }
+            let (key, value) = line.split_once('=')
+                .ok_or_else(|| invalid(i, format!("expected key = value, got {}", line)))?;
+            let (key, value) = (key.trim(), value.trim());
+            match key {
+                "name" => level.name = value.to_string(),
+                "size" => size = Some((i, parse_size(value)
+                    .ok_or_else(|| invalid(i, format!("invalid size: {}", value)))?)),
+                _ => level.settings.push((i + 1, key.to_string(), value.to_string())),
+            }
+        }
+
+        let mut rows = Vec::new();
+        for (i, line) in lines {
+            let line = line.trim_end();
+            if line.is_empty() {
+                continue;
+            }
+            let width = line.chars().count();
+            let y = rows.len();
+            for (x, tile) in line.chars().enumerate() {
+                let cell = Vec2::new(x, y);
+                match Tile::from_char(tile) {
+                    Some(Tile::Empty) => {}
+                    Some(Tile::Block) => level.blocks.push(cell),
+                    Some(Tile::Wall)  => level.walls.push(cell),
+                    Some(Tile::Fruit) => level.fruits.push(cell),
+                    Some(Tile::Snake(direction)) => {
+                        if snake.is_some() {
+                            return Err(invalid(i, "more than one snake start".to_string()));
+                        }
+                        snake = Some((cell, direction));
+                    }
+                    None => return Err(invalid(i, format!("unknown tile: {}", tile))),
+                }
+            }
+            if !rows.is_empty() && width != level.width {
+                return Err(invalid(i, format!("row is {} wide, the ones above are {}",
+                                              width, level.width)));
+            }
+            level.width = width;
+            rows.push(line);
+        }
+        level.height = rows.len();
+        let end = contents.lines().count();
+        if level.height == 0 {
+            return Err(LevelError::Invalid { line: end, reason: "missing map".to_string() });
+        }
+        level.snake = snake.ok_or(LevelError::Invalid {
+            line: end,
+            reason: "missing snake start".to_string(),
+        })?;
+        if let Some((i, size)) = size {
+            if size != (level.width, level.height) {
+                return Err(invalid(i, format!("size is {}x{}, the map is {}x{}",
+                                              size.0, size.1, level.width, level.height)));
+            }
+        }
+        Ok(level)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = String::new();
+        if !self.name.is_empty() {
+            out += &format!("name = {}\n", self.name);
+        }
+        out += &format!("size = {}x{}\n", self.width, self.height);
+        for (_, key, value) in self.settings.iter() {
+            out += &format!("{} = {}\n", key, value);
+        }
+        out += "\nmap\n";
+        for row in self.rows() {
+            out += &row;
+            out += "\n";
+        }
+        fs::write(path, out)
+    }
+
+    pub fn tile(&self, cell: Vec2<usize>) -> Tile {
+        if self.snake.0 == cell {
+            Tile::Snake(self.snake.1)
+        } else if self.blocks.contains(&cell) {
+            Tile::Block
+        } else if self.walls.contains(&cell) {
+            Tile::Wall
+        } else if self.fruits.contains(&cell) {
+            Tile::Fruit
+        } else {
+            Tile::Empty
+        }
+    }
+
+    // Puts `tile` on `cell` in place of whatever was there. There is always
+    // exactly one snake start, so it can be moved but not painted over.
+    pub fn set_tile(&mut self, cell: Vec2<usize>, tile: Tile) {
+        if let Tile::Snake(direction) = tile {
+            self.clear(cell);
+            self.snake = (cell, direction);
+            return;
+        }
+        if self.snake.0 == cell {
+            return;
+        }
+        self.clear(cell);
+        match tile {
+            Tile::Block => self.blocks.push(cell),
+            Tile::Wall  => self.walls.push(cell),
+            Tile::Fruit => self.fruits.push(cell),
+            Tile::Empty | Tile::Snake(_) => {}
+        }
+    }
+
+    fn clear(&mut self, cell: Vec2<usize>) {
+        self.blocks.retain(|other| *other != cell);
+        self.walls.retain(|other| *other != cell);
+        self.fruits.retain(|other| *other != cell);
+    }
+
+    // Changes the size of the board, dropping whatever ends up off it. The
+    // snake start is pulled back onto the board if it would.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        let inside = |cell: &Vec2<usize>| cell.x < width && cell.y < height;
+        self.blocks.retain(inside);
+        self.walls.retain(inside);
+        self.fruits.retain(inside);
+        let (start, direction) = self.snake;
+        let start = Vec2::new(start.x.min(width - 1), start.y.min(height - 1));
+        self.set_tile(start, Tile::Snake(direction));
+    }
+
+    fn rows(&self) -> Vec<String> {
+        (0..self.height)
+            .map(|y| (0..self.width).map(|x| self.tile(Vec2::new(x, y)).to_char()).collect())
+            .collect()
+    }
+
+    // Identifies the layout, so replays can tell whether they were recorded
+    // on it.
+    pub fn checksum(&self) -> u64 {
+        checksum(&self.rows().join("\n"))
+    }
+
+    // Sets the board size and the level's settings in `config`, and makes
+    // it start from this level.
+    pub fn apply(self, config: &mut GameConfig) -> Result<(), LevelError> {
+        config.map_width = self.width;
+        config.map_height = self.height;
+        for (line, key, value) in self.settings.iter() {
+            config.set(key, value)
+                .map_err(|reason| LevelError::Invalid { line: *line, reason })?;
+        }
+        config.level = Some(self);
+        Ok(())
+    }
+}
+
+fn invalid(index: usize, reason: String) -> LevelError {
+    LevelError::Invalid { line: index + 1, reason }
+}
+
+fn parse_size(value: &str) -> Option<(usize, usize)> {
+    let (width, height) = value.split_once('x')?;
+    Some((width.trim().parse().ok()?, height.trim().parse().ok()?))
+}
+
+// FNV-1a, which is stable across builds unlike the standard library's hasher.
+fn checksum(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
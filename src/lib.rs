@@ -0,0 +1,6 @@
+pub mod config;
+pub mod level;
+pub mod replay;
+pub mod score;
+pub mod simulation;
+pub mod spawner;
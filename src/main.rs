@@ -1,151 +1,316 @@
-use std::time::{Duration, Instant};
+mod args;
+mod editor;
+mod highscores;
+
+use std::path::{Path, PathBuf};
+use std::process;
+
 use tetra::graphics::{self, Color, Texture};
+use tetra::graphics::text::{Font, Text};
 use tetra::input::{self, Key};
+use tetra::time::Timestep;
 use tetra::{Context, ContextBuilder, State};
 use tetra::math::Vec2;
-use array2d::Array2D;
-use fastrand;
 
-const WINDOW_WIDTH:  f32 = 480.0;
-const WINDOW_HEIGHT: f32 = 480.0;
-const MAP_WIDTH:  usize = 9;
-const MAP_HEIGHT: usize = 9;
-const SPEED: u64 = 333;
+use snektris::config::{GameConfig, DEFAULT_CONFIG_FILE};
+use snektris::level::Level;
+use snektris::replay::Replay;
+use snektris::simulation::{Cell, Direction, FruitKind, Input, PlayState, Simulation, BLOCK_COLORS, TICK_RATE};
+
+use args::Args;
+use editor::Editor;
+use highscores::{HighScore, HighScores, MAX_NAME_LENGTH};
 
 fn main() -> tetra::Result {
-    ContextBuilder::new("stupid fuck_v2", WINDOW_WIDTH as i32, WINDOW_HEIGHT as i32)
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("snektris: {}", error);
+            process::exit(2);
+        }
+    };
+    let config = match load_config(&args) {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("snektris: {}", error);
+            process::exit(1);
+        }
+    };
+    let playback = match &args.replay {
+        Some(path) => match Replay::load(path, &config) {
+            Ok(replay) => Some(replay),
+            Err(error) => {
+                eprintln!("snektris: {}: {}", path.display(), error);
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let window_width = config.window_width as i32;
+    let window_height = config.window_height as i32;
+    let edit = args.edit;
+    let options = Options {
+        config,
+        record: args.record,
+        playback,
+        play_test: false,
+    };
+
+    ContextBuilder::new("stupid fuck_v2", window_width, window_height)
         .multisampling(8)
         .quit_on_escape(true)
+        .timestep(Timestep::Fixed(TICK_RATE as f64))
         .build()?
-        .run(GameState::new)
-}
-
-#[derive(PartialEq, Clone, Copy)]
-enum Direction {
-    Up,
-    Down,
-    Left,
-    Right
-}
-
-struct Snake {
-    body: Vec<Vec2<usize>>,
-    length: usize,
-    last_move: Instant,
-    direction_queue: Vec<Direction>,
-    disabled: bool,
+        .run(|ctx| match edit {
+            Some(path) => Ok(Scene::Editor(Box::new(Editor::new(ctx, options.config, path)?), None)),
+            None => Ok(Scene::Game(Box::new(GameState::new(ctx, options)?))),
+        })
 }
 
-impl Snake {
-    fn new() -> Snake {
-        let length = 3;
-        let body = vec![Vec2::new(3, 1); length];
-        Snake {
-            body,
-            length,
-            last_move: Instant::now(),
-            direction_queue: vec!(Direction::Right),
-            disabled: false,
-        }
+// The config file named on the command line, or the default one if it
+// exists, then the level if one was given, with any command line settings
+// applied on top.
+fn load_config(args: &Args) -> Result<GameConfig, String> {
+    let path = args.config.as_deref()
+        .or_else(|| Some(Path::new(DEFAULT_CONFIG_FILE)).filter(|path| path.exists()));
+    let mut config = match path {
+        Some(path) => GameConfig::load(path)
+            .map_err(|error| format!("{}: {}", path.display(), error))?,
+        None => GameConfig::default(),
+    };
+    if let Some(path) = &args.level {
+        Level::load(path)
+            .and_then(|level| level.apply(&mut config))
+            .map_err(|error| format!("{}: {}", path.display(), error))?;
     }
-
-    fn update(&mut self) {
-        let head: Vec2<i32> = self.body.first().unwrap().as_();
-        let new = match &self.direction_queue.first().unwrap() {
-            Direction::Up    => head + Vec2::new(0, -1),
-            Direction::Down  => head + Vec2::new(0, 1),
-            Direction::Left  => head + Vec2::new(-1, 0),
-            Direction::Right => head + Vec2::new(1, 0),
-        };
-        self.body.insert(0, new.as_());
-        if self.body.len() > self.length {
-            self.body.pop();
-        }
+    for (key, value) in args.overrides.iter() {
+        config.set(key, value)?;
     }
+    config.validate()?;
+    Ok(config)
 }
 
-#[derive(PartialEq)]
-struct FallingBlocks {
-    body: Vec<Vec2<usize>>,
-    length: usize,
-    last_move: Instant,
-    hit_ground: bool,
-}
-
-impl FallingBlocks {
-    fn from_snake(snake: &Snake) -> FallingBlocks {
-        FallingBlocks {
-            length: snake.length,
-            body: snake.body.clone(),
-            last_move: Instant::now(),
-            hit_ground: false,
-        }
+fn fruit_color(kind: FruitKind) -> Color {
+    match kind {
+        FruitKind::Normal     => Color::rgb(1.0, 0.5, 0.5),
+        FruitKind::Golden     => Color::rgb(1.0, 0.85, 0.2),
+        FruitKind::Shrinking  => Color::rgb(0.7, 0.4, 1.0),
+        FruitKind::Bomb       => Color::rgb(0.15, 0.15, 0.15),
+        FruitKind::SlowMotion => Color::rgb(0.4, 0.8, 1.0),
     }
-
-    fn update(&mut self, map: &Array2D<bool>) {
-        for part in self.body.iter() {
-            if part.y >= MAP_HEIGHT - 1 || map[(part.x, part.y + 1)] {
-                self.hit_ground = true;
-                return;
-            }
-        }
-        for part in self.body.iter_mut() {
-            part.y += 1;
-        }
-    }
-}
-
-#[derive(PartialEq)]
-struct Fruit {
-    pos: Vec2<usize>,
 }
 
-impl Fruit {
-    fn new(pos: Vec2<usize>) -> Fruit {
-        Fruit {
-            pos
-        }
-    }
+// Colors for the blocks, by the palette index they carry. The first is for
+// the blocks a level starts with.
+const BLOCK_PALETTE: [Color; BLOCK_COLORS as usize + 1] = [
+    Color::rgb(0.5, 0.5, 0.5),
+    Color::rgb(0.85, 0.4, 0.4),
+    Color::rgb(0.9, 0.6, 0.3),
+    Color::rgb(0.85, 0.8, 0.35),
+    Color::rgb(0.45, 0.75, 0.4),
+    Color::rgb(0.3, 0.7, 0.7),
+    Color::rgb(0.35, 0.45, 0.8),
+    Color::rgb(0.6, 0.4, 0.8),
+    Color::rgb(0.85, 0.5, 0.7),
+];
+
+// Everything a restart needs to set the same kind of game up again.
+#[derive(Clone)]
+struct Options {
+    config: GameConfig,
+    record: Option<PathBuf>,
+    playback: Option<Replay>,
+    // Started from the editor, so it goes back there instead of keeping
+    // high scores.
+    play_test: bool,
 }
 
-enum PlayState {
-    Normal,
-    Falling,
+// What the player is looking at, on top of the board.
+enum Screen {
+    Playing,
+    NameEntry(String),
+    GameOver,
+    HighScores,
 }
 
 struct GameState {
-    snake: Snake,
-    map: Array2D<bool>,
-    fruits: Vec<Fruit>,
-    falling_blocks: Option<FallingBlocks>,
+    simulation: Simulation,
+    screen: Screen,
+    high_scores: HighScores,
     texture: Texture,
-    state: PlayState,
+    font: Font,
+    small_font: Font,
+    options: Options,
 }
 
 impl GameState {
-    fn new(ctx: &mut Context) -> tetra::Result<GameState> {
-        let map = Array2D::filled_with(
-            true, MAP_WIDTH, MAP_HEIGHT);
-        let fruits = vec!(Fruit::new(Vec2::new(fastrand::usize(0..(MAP_WIDTH as usize)), 
-                                               fastrand::usize(0..(MAP_HEIGHT as usize)))));
+    fn new(ctx: &mut Context, options: Options) -> tetra::Result<GameState> {
         let texture = Texture::new(ctx, "./snake.png")?;
+        let font = Font::vector(ctx, "./DejaVuSansMono.ttf", 24.0)?;
+        let small_font = Font::vector(ctx, "./DejaVuSansMono.ttf", 16.0)?;
+        let seed = match &options.playback {
+            Some(replay) => replay.seed,
+            None => options.config.seed.unwrap_or_else(|| fastrand::u64(..)),
+        };
+
+        let high_scores = HighScores::load().unwrap_or_else(|error| {
+            eprintln!("snektris: could not load high scores: {}", error);
+            HighScores { entries: Vec::new() }
+        });
 
         Ok(GameState {
-            snake: Snake::new(),
-            map,
-            fruits,
-            falling_blocks: None,
+            simulation: Simulation::new(options.config.clone(), seed),
+            screen: Screen::Playing,
+            high_scores,
             texture,
-            state: PlayState::Normal,
+            font,
+            small_font,
+            options,
         })
     }
 
-    fn blocks_to_map(&mut self) {
-        if let Some(falling_blocks) = &self.falling_blocks {
-            for block in falling_blocks.body.iter() {
-                self.map[(block.x, block.y)] = true;
+    fn save_replay(&self) {
+        if self.options.playback.is_some() {
+            return;
+        }
+        if let Some(path) = &self.options.record {
+            if let Err(error) = self.simulation.replay.save(path) {
+                eprintln!("snektris: could not save replay to {}: {}", path.display(), error);
+            }
+        }
+    }
+
+    fn save_high_score(&mut self, name: String) {
+        let simulation = &self.simulation;
+        self.high_scores.insert(HighScore {
+            name,
+            points: simulation.score.points,
+            lines: simulation.score.lines,
+            fruits: simulation.score.fruits,
+            max_length: simulation.score.max_length,
+            seed: simulation.seed,
+            width: simulation.config.map_width,
+            height: simulation.config.map_height,
+            date: highscores::now(),
+        });
+        if let Err(error) = self.high_scores.save() {
+            eprintln!("snektris: could not save high scores: {}", error);
+        }
+    }
+
+    fn update_playing(&mut self, ctx: &mut Context) {
+        let inputs: Vec<Input> = match &self.options.playback {
+            Some(replay) => replay.inputs_at(self.simulation.tick),
+            None => {
+                let falling = self.simulation.state == PlayState::Falling;
+                input::get_keys_pressed(ctx)
+                    .filter_map(|key| if falling {
+                        match key {
+                            Key::Left  => Some(Input::MoveLeft),
+                            Key::Right => Some(Input::MoveRight),
+                            Key::Down  => Some(Input::SoftDrop),
+                            Key::Space => Some(Input::HardDrop),
+                            Key::Up | Key::X => Some(Input::RotateClockwise),
+                            Key::Z     => Some(Input::RotateCounterClockwise),
+                            _ => None,
+                        }
+                    } else {
+                        match key {
+                            Key::Up    => Some(Input::Turn(Direction::Up)),
+                            Key::Down  => Some(Input::Turn(Direction::Down)),
+                            Key::Left  => Some(Input::Turn(Direction::Left)),
+                            Key::Right => Some(Input::Turn(Direction::Right)),
+                            Key::Space => Some(Input::Detach),
+                            _ => None,
+                        }
+                    })
+                    .collect()
+            }
+        };
+        self.simulation.step(&inputs);
+        if let PlayState::GameOver(_) = self.simulation.state {
+            self.save_replay();
+            self.screen = if self.options.playback.is_none() && !self.options.play_test &&
+                             self.high_scores.qualifies(self.simulation.score.points) {
+                Screen::NameEntry(String::new())
+            } else {
+                Screen::GameOver
+            };
+        }
+    }
+
+    fn update_name_entry(&mut self, ctx: &mut Context) {
+        if let Screen::NameEntry(name) = &mut self.screen {
+            if let Some(text) = input::get_text_input(ctx) {
+                for c in text.chars().filter(|c| !c.is_control()) {
+                    if name.chars().count() < MAX_NAME_LENGTH {
+                        name.push(c);
+                    }
+                }
+            }
+            if input::is_key_pressed(ctx, Key::Backspace) {
+                name.pop();
+            }
+            if input::is_key_pressed(ctx, Key::Enter) && !name.trim().is_empty() {
+                let name = name.trim().to_string();
+                self.save_high_score(name);
+                self.screen = Screen::HighScores;
+            }
+        }
+    }
+
+    fn draw_hud(&mut self, ctx: &mut Context) {
+        let score = &self.simulation.score;
+        let goal = self.simulation.config.goal;
+        let lines = if goal > 0 {
+            format!("{}/{}", score.lines, goal)
+        } else {
+            score.lines.to_string()
+        };
+        let mut hud = format!("Level: {}  Score: {}  Lines: {}  Fruits: {}",
+                              self.simulation.level(), score.points, lines, score.fruits);
+        if let Some(level) = &self.simulation.config.level {
+            hud = format!("{}\n{}", level.name, hud);
+        }
+        let mut text = Text::new(hud, self.small_font.clone());
+        text.draw(ctx, Vec2::new(4.0, 4.0));
+    }
+
+    fn draw_game_over(&mut self, ctx: &mut Context) {
+        if let PlayState::GameOver(reason) = self.simulation.state {
+            let mut message = format!("GAME OVER\n\n{}\nScore: {}\nSeed: {}\n\n\
+                                       Press R to restart\nPress H for high scores",
+                                      reason.description(), self.simulation.score.points,
+                                      self.simulation.seed);
+            if self.options.play_test {
+                message += "\nPress Tab to edit";
             }
+            let mut text = Text::new(message, self.font.clone());
+            text.draw(ctx, Vec2::new(16.0, self.simulation.config.window_height / 3.0));
+        }
+    }
+
+    fn draw_name_entry(&mut self, ctx: &mut Context, name: &str) {
+        let mut text = Text::new(format!("NEW HIGH SCORE\n\nScore: {}\n\nName: {}_\n\n\
+                                          Press Enter to save",
+                                         self.simulation.score.points, name),
+                                 self.font.clone());
+        text.draw(ctx, Vec2::new(16.0, self.simulation.config.window_height / 3.0));
+    }
+
+    fn draw_high_scores(&mut self, ctx: &mut Context) {
+        let mut table = String::from("HIGH SCORES\n\n");
+        for (rank, entry) in self.high_scores.entries.iter().enumerate() {
+            table += &format!("{:2}. {:<12} {:>6}  L{:<3} F{:<3} {}x{} {}\n",
+                              rank + 1, entry.name, entry.points, entry.lines, entry.fruits,
+                              entry.width, entry.height, entry.date_string());
         }
+        if self.high_scores.entries.is_empty() {
+            table += "No scores yet\n";
+        }
+        table += "\nPress R to restart\nPress B to go back";
+        let mut text = Text::new(table, self.small_font.clone());
+        text.draw(ctx, Vec2::new(8.0, 32.0));
     }
 }
 
@@ -153,13 +318,15 @@ impl State for GameState {
     fn draw(&mut self, ctx: &mut Context) -> tetra::Result {
         graphics::clear(ctx, Color::rgb(0.5, 0.5, 1.0));
         
-        let part_size = f32::min(WINDOW_WIDTH, WINDOW_HEIGHT) / 
-                        f32::min(MAP_WIDTH as f32, MAP_HEIGHT as f32);
+        let simulation = &self.simulation;
+        let config = &simulation.config;
+        let part_size = f32::min(config.window_width / config.map_width as f32,
+                                 config.window_height / config.map_height as f32);
         let scale = Vec2::new(part_size / self.texture.width()  as f32,
                                      part_size / self.texture.height() as f32);
 
-        if !self.snake.disabled {
-            for part in self.snake.body.iter() {
+        if !simulation.snake.disabled {
+            for part in simulation.snake.body.iter() {
                 let params = graphics::DrawParams::new()
                     .position(Vec2::new(part.x as f32 * part_size, part.y as f32 * part_size))
                     .scale(scale)
@@ -168,36 +335,65 @@ impl State for GameState {
             }
         }
 
-        if let Some(falling_blocks) = &self.falling_blocks {
+        let ghost = if config.landing_preview {
+            simulation.ghost().or_else(|| simulation.landing_preview())
+        } else {
+            simulation.ghost()
+        };
+        for part in ghost.iter().flatten() {
+            let params = graphics::DrawParams::new()
+                .position(Vec2::new(part.x as f32 * part_size, part.y as f32 * part_size))
+                .scale(scale)
+                .color(graphics::Color::rgba(0.5, 0.5, 0.5, 0.35));
+            self.texture.draw(ctx, params);
+        }
+
+        if let Some(falling_blocks) = &simulation.falling_blocks {
             for part in falling_blocks.body.iter() {
                 let params = graphics::DrawParams::new()
                     .position(Vec2::new(part.x as f32 * part_size, part.y as f32 * part_size))
                     .scale(scale)
-                    .color(graphics::Color::rgb(0.5, 0.5, 0.5));
+                    .color(BLOCK_PALETTE[falling_blocks.color as usize]);
                 self.texture.draw(ctx, params); 
             }
         }
 
 
-        for fruit in self.fruits.iter() {
+        for fruit in simulation.fruits.iter() {
             let params = graphics::DrawParams::new()
                 .position(Vec2::new(fruit.pos.x as f32 * part_size, fruit.pos.y as f32 * part_size))
                 .scale(scale)
-                .color(graphics::Color::rgb(1.0, 0.5, 0.5));
+                .color(fruit_color(fruit.kind));
             self.texture.draw(ctx, params); 
         }
 
 
-        for y in 0..(MAP_HEIGHT) {
-            for x in 0..(MAP_WIDTH) {
-                if self.map[(x, y)] {
-                    let coords = Vec2::new(x, y);
-                    let params = graphics::DrawParams::new()
-                        .position(Vec2::new(coords.x as f32 * part_size, coords.y as f32 * part_size))
-                        .scale(scale)
-                        .color(graphics::Color::rgb(0.5, 0.5, 0.5));
-                    self.texture.draw(ctx, params); 
-                }
+        for y in 0..config.map_height {
+            for x in 0..config.map_width {
+                let color = match simulation.map[(x, y)] {
+                    Cell::Empty               => continue,
+                    Cell::Block { color, .. } => BLOCK_PALETTE[color as usize],
+                    Cell::Wall                => graphics::Color::rgb(0.25, 0.25, 0.3),
+                };
+                let coords = Vec2::new(x, y);
+                let params = graphics::DrawParams::new()
+                    .position(Vec2::new(coords.x as f32 * part_size, coords.y as f32 * part_size))
+                    .scale(scale)
+                    .color(color);
+                self.texture.draw(ctx, params); 
+            }
+        }
+
+        match &self.screen {
+            Screen::Playing => self.draw_hud(ctx),
+            Screen::NameEntry(name) => {
+                let name = name.clone();
+                self.draw_name_entry(ctx, &name);
+            }
+            Screen::GameOver => self.draw_game_over(ctx),
+            Screen::HighScores => {
+                graphics::clear(ctx, Color::rgb(0.5, 0.5, 1.0));
+                self.draw_high_scores(ctx);
             }
         }
 
@@ -205,125 +401,58 @@ impl State for GameState {
     }
 
     fn update(&mut self, ctx: &mut Context) -> tetra::Result {
-        match self.state {
-            PlayState::Normal => {
-                let mut to_remove = Vec::new();
-                for y in 0..(MAP_HEIGHT) {
-                    let mut to_to_remove = true;
-                    for x in 0..(MAP_WIDTH) {
-                        if !self.map[(x, y)] {
-                            to_to_remove = false;
-                        }
-                    }
-                    if to_to_remove {
-                        to_remove.push(y);
-                    }
-                }
-                for y in to_remove.iter() {
-                    for x in 0..(MAP_WIDTH) {
-                        self.map[(x, *y)] = false;
-                    }
-                    for yy in (0..(*y)).rev() {
-                        for x in 0..(MAP_WIDTH) {
-                            self.map[(x, yy + 1)] = self.map[(x, yy)];
-                        }
-                    }
+        match self.screen {
+            Screen::Playing => self.update_playing(ctx),
+            Screen::NameEntry(_) => self.update_name_entry(ctx),
+            Screen::GameOver | Screen::HighScores => {
+                if input::is_key_pressed(ctx, Key::R) {
+                    *self = GameState::new(ctx, self.options.clone())?;
+                } else if input::is_key_pressed(ctx, Key::H) {
+                    self.screen = Screen::HighScores;
+                } else if input::is_key_pressed(ctx, Key::B) {
+                    self.screen = Screen::GameOver;
                 }
+            }
+        }
+        Ok(())
+    }
+}
 
-                if !self.snake.disabled {
-                    let mut fruits_to_retain = Vec::new();
-                    let head = *self.snake.body.first().unwrap();
-                    for fruit in self.fruits.iter_mut() {
-                        if fruit.pos == head {
-                            self.falling_blocks = Some(FallingBlocks::from_snake(&self.snake));
-                            self.snake.disabled = true;
-                            fruits_to_retain.push(false);
-                            self.state = PlayState::Falling;
-                        } else {
-                            fruits_to_retain.push(true);
-                        }
-                    }
-
-                    let mut count = 0;
-                    for bodypart in 1..self.snake.body.len() {
-                        if head == self.snake.body[bodypart] {
-                            count += 1;
-                        }
-                    }
-                    //ugly
-                    let coords = head.into_tuple();
-                    if head.x > MAP_WIDTH  || head.x < 0 || 
-                       head.y > MAP_HEIGHT || head.y < 0 || 
-                       count == 1 || self.map[coords] {
-                        println!("{}", head);
-                        panic!();
-                    }
+// What the window shows: a game, or the editor along with the game being
+// play-tested from it, if any.
+enum Scene {
+    Game(Box<GameState>),
+    Editor(Box<Editor>, Option<Box<GameState>>),
+}
 
-                    for key in input::get_keys_pressed(ctx) {
-                        let current_direction = self.snake.direction_queue.last().unwrap();
-                        let next_direction = match key {
-                            Key::Up    => Direction::Up,
-                            Key::Down  => Direction::Down,
-                            Key::Left  => Direction::Left,
-                            Key::Right => Direction::Right,
-                            _ => *current_direction,
-                        };
-
-                        let forbidden_direction = match current_direction {
-                            Direction::Up    => Direction::Down,
-                            Direction::Down  => Direction::Up,
-                            Direction::Left  => Direction::Right,
-                            Direction::Right => Direction::Left,
-                        };
-                        
-                        if next_direction != forbidden_direction &&
-                           next_direction != *current_direction {
-                            self.snake.direction_queue.push(next_direction);
-                        }
-                    }
+impl State for Scene {
+    fn draw(&mut self, ctx: &mut Context) -> tetra::Result {
+        match self {
+            Scene::Game(game) | Scene::Editor(_, Some(game)) => game.draw(ctx)?,
+            Scene::Editor(editor, None) => editor.draw(ctx),
+        }
+        Ok(())
+    }
 
-                    if self.snake.last_move.elapsed() >= Duration::from_millis(SPEED) {
-                        if self.snake.direction_queue.len() > 1 {
-                            self.snake.direction_queue.remove(0);
-                        }
-                        self.snake.direction_queue.truncate(2);
-                        self.snake.update();
-                        self.snake.last_move = Instant::now();
-                    }
-                    let mut iter = fruits_to_retain.iter();
-                    self.fruits.retain(|_| *iter.next().unwrap());
+    fn update(&mut self, ctx: &mut Context) -> tetra::Result {
+        match self {
+            Scene::Game(game) => game.update(ctx)?,
+            Scene::Editor(_, play_test @ Some(_)) => {
+                if input::is_key_pressed(ctx, Key::Tab) {
+                    *play_test = None;
+                } else if let Some(game) = play_test {
+                    game.update(ctx)?;
                 }
             }
-            PlayState::Falling => {
-                let mut fallen = false;
-                if let Some(falling_blocks) = &mut self.falling_blocks {
-                    if falling_blocks.hit_ground {
-                        fallen = true;
-                    }
-
-                    if falling_blocks.last_move.elapsed() >= Duration::from_millis(SPEED / 3) {
-                        falling_blocks.update(&self.map);
-                        falling_blocks.last_move = Instant::now();
-                    }
-                }
-                if fallen {
-                    self.blocks_to_map();
-                    self.falling_blocks = None;
-                    self.snake.disabled = false;
-                    self.snake.length += 1;
-                    self.snake.body.fill(Vec2::new(MAP_WIDTH / 2, 0));
-                    self.snake.direction_queue[0] = Direction::Down;
-
-
-                    //ugly
-                    let mut coords = (fastrand::usize(0..(MAP_WIDTH)), 
-                                  fastrand::usize(0..(MAP_HEIGHT)));
-                    while self.map[coords] {
-                        coords = (fastrand::usize(0..(MAP_WIDTH)), 
-                                  fastrand::usize(0..(MAP_HEIGHT)));
-                    }
-                    self.fruits.push(Fruit::new(Vec2::from(coords)));
-                    self.state = PlayState::Normal;
+            Scene::Editor(editor, play_test) => {
+                if let Some(config) = editor.update(ctx) {
+                    let options = Options {
+                        config,
+                        record: None,
+                        playback: None,
+                        play_test: true,
+                    };
+                    *play_test = Some(Box::new(GameState::new(ctx, options)?));
                 }
             }
         }
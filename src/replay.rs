@@ -0,0 +1,344 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::config::GameConfig;
+use crate::score::Score;
+use crate::simulation::{Direction, Input};
+
+// Bump this whenever a change to the rules or the file layout would make
+// older replays play out differently.
+pub const REPLAY_VERSION: u32 = 19;
+
+const MAGIC: &str = "snektris-replay";
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    Malformed { line: usize, reason: String },
+    Version(u32),
+    BoardSize { width: usize, height: usize, expected: (usize, usize) },
+    Rule { key: String, recorded: Option<String>, expected: String },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Io(error) =>
+                write!(f, "could not read replay: {}", error),
+            ReplayError::Malformed { line, reason } =>
+                write!(f, "malformed replay on line {}: {}", line, reason),
+            ReplayError::Version(version) =>
+                write!(f, "replay has format version {}, this build plays version {}",
+                       version, REPLAY_VERSION),
+            ReplayError::BoardSize { width, height, expected } =>
+                write!(f, "replay was recorded on a {}x{} board, the current config uses {}x{}",
+                       width, height, expected.0, expected.1),
+            ReplayError::Rule { key, recorded: Some(recorded), expected } =>
+                write!(f, "replay was recorded with {} = {}, the current config uses {}",
+                       key, recorded, expected),
+            ReplayError::Rule { key, recorded: None, expected } =>
+                write!(f, "replay does not record {}, the current config uses {}",
+                       key, expected),
+        }
+    }
+}
+
+impl From<io::Error> for ReplayError {
+    fn from(error: io::Error) -> ReplayError {
+        ReplayError::Io(error)
+    }
+}
+
+#[derive(Clone)]
+pub struct Replay {
+    pub seed: u64,
+    pub width: usize,
+    pub height: usize,
+    // The rest of the settings that decide how the game plays out.
+    pub rules: Vec<(String, String)>,
+    // Every input the simulation acted on, with the tick it arrived on.
+    // Kept in tick order.
+    pub inputs: Vec<(u64, Input)>,
+    // How the recorded game ended, once it has.
+    pub score: Option<Score>,
+}
+
+impl Replay {
+    pub fn new(config: &GameConfig, seed: u64) -> Replay {
+        Replay {
+            seed,
+            width: config.map_width,
+            height: config.map_height,
+            rules: config.rules().into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+            inputs: Vec::new(),
+            score: None,
+        }
+    }
+
+    pub fn record(&mut self, tick: u64, input: Input) {
+        self.inputs.push((tick, input));
+    }
+
+    pub fn inputs_at(&self, tick: u64) -> Vec<Input> {
+        let start = self.inputs.partition_point(|(t, _)| *t < tick);
+        self.inputs[start..].iter()
+            .take_while(|(t, _)| *t == tick)
+            .map(|(_, input)| *input)
+            .collect()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = format!("{} {}\n", MAGIC, REPLAY_VERSION);
+        out += &format!("seed {}\n", self.seed);
+        out += &format!("board {} {}\n", self.width, self.height);
+        for (key, value) in self.rules.iter() {
+            out += &format!("rule {} {}\n", key, value);
+        }
+        for (tick, input) in self.inputs.iter() {
+            out += &format!("{} {}\n", tick, format_input(*input));
+        }
+        if let Some(score) = &self.score {
+            out += &format!("score {} {} {} {}\n",
+                            score.points, score.lines, score.fruits, score.max_length);
+        }
+        fs::write(path, out)
+    }
+
+    // Loads a replay and checks that it was recorded under the rules in
+    // `config`, since playing it under any others would not reproduce it.
+    pub fn load<P: AsRef<Path>>(path: P, config: &GameConfig) -> Result<Replay, ReplayError> {
+        let contents = fs::read_to_string(path)?;
+        let mut lines = contents.lines().enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty());
+
+        let mut header = |name: &str| -> Result<(usize, Vec<&str>), ReplayError> {
+            let (number, line) = lines.next().ok_or(ReplayError::Malformed {
+                line: 0,
+                reason: format!("missing {}", name),
+            })?;
+            let mut words = line.split_whitespace();
+            if words.next() != Some(name) {
+                return Err(malformed(number, format!("expected {}", name)));
+            }
+            Ok((number, words.collect()))
+        };
+
+        let (number, words) = header(MAGIC)?;
+        let version = parse_field(number, words.first().copied())?;
+        if version != REPLAY_VERSION {
+            return Err(ReplayError::Version(version));
+        }
+
+        let (number, words) = header("seed")?;
+        let seed = parse_field(number, words.first().copied())?;
+
+        let (number, words) = header("board")?;
+        let width = parse_field(number, words.first().copied())?;
+        let height = parse_field(number, words.get(1).copied())?;
+        if width != config.map_width || height != config.map_height {
+            return Err(ReplayError::BoardSize {
+                width,
+                height,
+                expected: (config.map_width, config.map_height),
+            });
+        }
+
+        let mut replay = Replay {
+            seed,
+            width,
+            height,
+            rules: Vec::new(),
+            inputs: Vec::new(),
+            score: None,
+        };
+        for (number, line) in lines {
+            let mut words = line.split_whitespace();
+            if line.starts_with("rule") {
+                words.next();
+                let key = words.next().ok_or_else(|| malformed(number, "missing rule".to_string()))?;
+                let value: String = parse_field(number, words.next())?;
+                replay.rules.push((key.to_string(), value));
+                continue;
+            }
+            if line.starts_with("score") {
+                words.next();
+                replay.score = Some(Score {
+                    points: parse_field(number, words.next())?,
+                    lines: parse_field(number, words.next())?,
+                    fruits: parse_field(number, words.next())?,
+                    max_length: parse_field(number, words.next())?,
+                });
+                continue;
+            }
+            let tick = parse_field(number, words.next())?;
+            let input = parse_input(&words.collect::<Vec<_>>())
+                .ok_or_else(|| malformed(number, format!("unknown input: {}", line)))?;
+            if matches!(replay.inputs.last(), Some((last, _)) if *last > tick) {
+                return Err(malformed(number, "inputs are out of order".to_string()));
+            }
+            replay.record(tick, input);
+        }
+
+        for (key, expected) in config.rules() {
+            let recorded = replay.rules.iter()
+                .find(|(recorded_key, _)| recorded_key == key)
+                .map(|(_, value)| value.clone());
+            if recorded.as_ref() != Some(&expected) {
+                return Err(ReplayError::Rule { key: key.to_string(), recorded, expected });
+            }
+        }
+
+        Ok(replay)
+    }
+}
+
+fn malformed(line: usize, reason: String) -> ReplayError {
+    ReplayError::Malformed { line, reason }
+}
+
+fn parse_field<T: std::str::FromStr>(line: usize, word: Option<&str>) -> Result<T, ReplayError> {
+    let word = word.ok_or_else(|| malformed(line, "missing value".to_string()))?;
+    word.parse().map_err(|_| malformed(line, format!("invalid value: {}", word)))
+}
+
+fn format_input(input: Input) -> String {
+    match input {
+        Input::Turn(direction) => format!("turn {}", format_direction(direction)),
+        Input::MoveLeft  => "move left".to_string(),
+        Input::MoveRight => "move right".to_string(),
+        Input::SoftDrop  => "soft-drop".to_string(),
+        Input::HardDrop  => "hard-drop".to_string(),
+        Input::RotateClockwise        => "rotate cw".to_string(),
+        Input::RotateCounterClockwise => "rotate ccw".to_string(),
+        Input::Detach => "detach".to_string(),
+    }
+}
+
+fn parse_input(words: &[&str]) -> Option<Input> {
+    match words {
+        ["turn", direction] => Some(Input::Turn(parse_direction(direction)?)),
+        ["move", "left"]    => Some(Input::MoveLeft),
+        ["move", "right"]   => Some(Input::MoveRight),
+        ["soft-drop"]       => Some(Input::SoftDrop),
+        ["hard-drop"]       => Some(Input::HardDrop),
+        ["rotate", "cw"]    => Some(Input::RotateClockwise),
+        ["rotate", "ccw"]   => Some(Input::RotateCounterClockwise),
+        ["detach"]          => Some(Input::Detach),
+        _ => None,
+    }
+}
+
+fn format_direction(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Up    => "up",
+        Direction::Down  => "down",
+        Direction::Left  => "left",
+        Direction::Right => "right",
+    }
+}
+
+fn parse_direction(word: &str) -> Option<Direction> {
+    match word {
+        "up"    => Some(Direction::Up),
+        "down"  => Some(Direction::Down),
+        "left"  => Some(Direction::Left),
+        "right" => Some(Direction::Right),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::path::PathBuf;
+
+    use super::*;
+
+    // A file in the temp directory that no other test writes to.
+    fn temp_file(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("snektris-{}-{}.replay", name, std::process::id()))
+    }
+
+    fn recorded(config: &GameConfig) -> Replay {
+        let mut replay = Replay::new(config, 42);
+        let inputs = [
+            Input::Turn(Direction::Left),
+            Input::Turn(Direction::Up),
+            Input::Detach,
+            Input::MoveLeft,
+            Input::MoveRight,
+            Input::SoftDrop,
+            Input::RotateClockwise,
+            Input::RotateCounterClockwise,
+            Input::HardDrop,
+        ];
+        for (tick, input) in inputs.iter().enumerate() {
+            replay.record(tick as u64 * 7, *input);
+        }
+        replay.score = Some(Score { points: 1234, lines: 5, fruits: 6, max_length: 7 });
+        replay
+    }
+
+    #[test]
+    fn saved_replays_load_back_the_same() {
+        let config = GameConfig::default();
+        let replay = recorded(&config);
+        let path = temp_file("round-trip");
+        replay.save(&path).unwrap();
+        let loaded = Replay::load(&path, &config).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.seed, replay.seed);
+        assert_eq!(loaded.inputs, replay.inputs);
+        assert_eq!(loaded.score, replay.score);
+        assert_eq!(loaded.rules, replay.rules);
+    }
+
+    #[test]
+    fn replays_from_another_version_are_rejected() {
+        let config = GameConfig::default();
+        let path = temp_file("version");
+        recorded(&config).save(&path).unwrap();
+        let contents = fs::read_to_string(&path).unwrap()
+            .replacen(&format!("{} {}", MAGIC, REPLAY_VERSION), &format!("{} 1", MAGIC), 1);
+        fs::write(&path, contents).unwrap();
+        let result = Replay::load(&path, &config);
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(ReplayError::Version(1))));
+    }
+
+    #[test]
+    fn replays_from_another_board_size_are_rejected() {
+        let path = temp_file("board-size");
+        recorded(&GameConfig::default()).save(&path).unwrap();
+        let config = GameConfig { map_width: 10, ..GameConfig::default() };
+        let result = Replay::load(&path, &config);
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(ReplayError::BoardSize { width: 9, height: 9, expected: (10, 9) })));
+    }
+
+    #[test]
+    fn replays_under_other_rules_are_rejected() {
+        let path = temp_file("rule");
+        recorded(&GameConfig::default()).save(&path).unwrap();
+        let config = GameConfig { speed: 200, ..GameConfig::default() };
+        let result = Replay::load(&path, &config);
+        fs::remove_file(&path).unwrap();
+
+        match result {
+            Err(ReplayError::Rule { key, recorded, expected }) => {
+                assert_eq!(key, "speed");
+                assert_eq!(recorded.as_deref(), Some("333"));
+                assert_eq!(expected, "200");
+            }
+            _ => panic!("expected a rule mismatch"),
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use crate::simulation::FruitKind;
+
+pub const FRUIT_POINTS: u64 = 10;
+pub const GOLDEN_FRUIT_POINTS: u64 = 100;
+pub const LENGTH_POINTS: u64 = 5;
+pub const BOARD_CLEAR_POINTS: u64 = 1000;
+
+// Points for clearing 1, 2, 3 or 4 rows at once. Every row past the fourth
+// adds another ROW_POINTS_EXTRA on top.
+const ROW_POINTS: [u64; 4] = [100, 300, 500, 800];
+const ROW_POINTS_EXTRA: u64 = 400;
+
+// Points for each block in a same-colored group cleared under the
+// color-match ruleset.
+const MATCH_POINTS: u64 = 20;
+
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct Score {
+    pub points: u64,
+    pub lines: u32,
+    pub fruits: u32,
+    pub max_length: usize,
+}
+
+impl Score {
+    pub fn fruit_eaten(&mut self, kind: FruitKind) {
+        self.fruits += 1;
+        self.points += match kind {
+            FruitKind::Golden => GOLDEN_FRUIT_POINTS,
+            _ => FRUIT_POINTS,
+        };
+    }
+
+    // The longer the snake when it turns into blocks, the more it pays.
+    pub fn dropped(&mut self, length: usize) {
+        self.points += LENGTH_POINTS * length as u64;
+        self.max_length = self.max_length.max(length);
+    }
+
+    // `chain` counts the clears set off by a single landing, starting at 1.
+    // Each link in the chain multiplies the points for its rows.
+    pub fn rows_cleared(&mut self, rows: usize, chain: u32) {
+        if rows == 0 {
+            return;
+        }
+        self.lines += rows as u32;
+        let points = match rows {
+            1..=4 => ROW_POINTS[rows - 1],
+            _ => ROW_POINTS[3] + ROW_POINTS_EXTRA * (rows - 4) as u64,
+        };
+        self.points += points * chain as u64;
+    }
+
+    // Same-colored groups pay by the block, multiplied by the chain like
+    // rows are.
+    pub fn groups_cleared(&mut self, cells: usize, chain: u32) {
+        self.points += MATCH_POINTS * cells as u64 * chain as u64;
+    }
+
+    // A detached snake pays no length points, but still counts as the
+    // longest one if it was.
+    pub fn detached(&mut self, cost: u64, length: usize) {
+        self.points = self.points.saturating_sub(cost);
+        self.max_length = self.max_length.max(length);
+    }
+
+    pub fn board_cleared(&mut self) {
+        self.points += BOARD_CLEAR_POINTS;
+    }
+}
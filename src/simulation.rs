@@ -0,0 +1,1440 @@
+use std::cmp::Reverse;
+use std::convert::TryFrom;
+
+use vek::Vec2;
+use array2d::Array2D;
+use fastrand::Rng;
+
+use crate::config::{BlockColors, DropMode, FruitRespawn, GameConfig, Gravity, RotationPivot,
+                    Ruleset};
+use crate::replay::Replay;
+use crate::score::Score;
+use crate::spawner;
+
+pub const TICK_RATE: u64 = 60;
+
+// Saturates rather than overflowing, so any interval the config allows
+// just becomes a very long wait.
+pub fn ms_to_ticks(ms: u64) -> u64 {
+    ms.saturating_mul(TICK_RATE).saturating_add(500) / 1000
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [Direction::Up, Direction::Down, Direction::Left, Direction::Right];
+
+    pub fn offset(self) -> Vec2<i32> {
+        match self {
+            Direction::Up    => Vec2::new(0, -1),
+            Direction::Down  => Vec2::new(0, 1),
+            Direction::Left  => Vec2::new(-1, 0),
+            Direction::Right => Vec2::new(1, 0),
+        }
+    }
+
+    pub fn opposite(self) -> Direction {
+        match self {
+            Direction::Up    => Direction::Down,
+            Direction::Down  => Direction::Up,
+            Direction::Left  => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Input {
+    Turn(Direction),
+    MoveLeft,
+    MoveRight,
+    SoftDrop,
+    HardDrop,
+    RotateClockwise,
+    RotateCounterClockwise,
+    Detach,
+}
+
+pub struct Snake {
+    pub body: Vec<Vec2<usize>>,
+    pub length: usize,
+    pub last_move: u64,
+    pub direction_queue: Vec<Direction>,
+    pub disabled: bool,
+}
+
+impl Snake {
+    fn new(start: Vec2<usize>, direction: Direction) -> Snake {
+        let length = 3;
+        let body = vec![start; length];
+        Snake {
+            body,
+            length,
+            last_move: 0,
+            direction_queue: vec!(direction),
+            disabled: false,
+        }
+    }
+
+    // Moves the head one cell forward. Returns false, leaving the snake
+    // where it was, if that would take the head off the board.
+    fn update(&mut self, config: &GameConfig) -> bool {
+        let head: Vec2<i32> = self.body.first().unwrap().as_();
+        let new = match wrap_cell(head + self.direction_queue.first().unwrap().offset(), config) {
+            Some(new) => new,
+            None => return false,
+        };
+        self.body.insert(0, new);
+        if self.body.len() > self.length {
+            self.body.pop();
+        }
+        true
+    }
+
+    // Right after a respawn every segment sits on the same cell, which is
+    // not a collision; the head running back into its own body is.
+    fn bites_itself(&self) -> bool {
+        let head = self.body[0];
+        self.body[1..].contains(&head) && !self.body.iter().all(|part| *part == head)
+    }
+
+    fn queue_direction(&mut self, next_direction: Direction) -> bool {
+        let current_direction = *self.direction_queue.last().unwrap();
+        if next_direction != current_direction.opposite() &&
+           next_direction != current_direction {
+            self.direction_queue.push(next_direction);
+            return true;
+        }
+        false
+    }
+}
+
+// Brings a cell that has gone off the board back onto it across the edges
+// that wrap. None if it went off one that does not.
+pub fn wrap_cell(cell: Vec2<i32>, config: &GameConfig) -> Option<Vec2<usize>> {
+    let (width, height) = (config.map_width as i32, config.map_height as i32);
+    let x = if config.wrap.horizontal() { cell.x.rem_euclid(width) } else { cell.x };
+    let y = if config.wrap.vertical() { cell.y.rem_euclid(height) } else { cell.y };
+    if x < 0 || x >= width || y < 0 || y >= height {
+        return None;
+    }
+    Some(Vec2::new(x as usize, y as usize))
+}
+
+// The cells the snake can move to from `cell`, which unlike `neighbours`
+// includes the ones across wrapping edges.
+pub fn snake_moves(cell: Vec2<usize>, config: &GameConfig) -> Vec<Vec2<usize>> {
+    Direction::ALL.iter()
+        .filter_map(|direction| wrap_cell(cell.as_::<i32>() + direction.offset(), config))
+        .collect()
+}
+
+pub fn neighbours(cell: Vec2<usize>, width: usize, height: usize) -> Vec<Vec2<usize>> {
+    let mut result = Vec::with_capacity(4);
+    if cell.x > 0 {
+        result.push(Vec2::new(cell.x - 1, cell.y));
+    }
+    if cell.x + 1 < width {
+        result.push(Vec2::new(cell.x + 1, cell.y));
+    }
+    if cell.y > 0 {
+        result.push(Vec2::new(cell.x, cell.y - 1));
+    }
+    if cell.y + 1 < height {
+        result.push(Vec2::new(cell.x, cell.y + 1));
+    }
+    result
+}
+
+// Blocks are colored 1 to BLOCK_COLORS; 0 is for blocks that were on the
+// board from the start.
+pub const BLOCK_COLORS: u8 = 8;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Cell {
+    Empty,
+    // Part of the stack. `color` is an index into the palette the blocks
+    // are drawn with; the other two say how long the snake it came from
+    // was and the tick it landed on.
+    Block { color: u8, origin_length: usize, placed_at: u64 },
+    // Never moves and is never cleared.
+    Wall,
+}
+
+impl Cell {
+    pub fn is_empty(self) -> bool {
+        self == Cell::Empty
+    }
+
+    pub fn is_block(self) -> bool {
+        matches!(self, Cell::Block { .. })
+    }
+}
+
+// Offsets tried in order when a rotated shape does not fit where it is.
+const KICKS: [(i32, i32); 7] = [(0, 0), (-1, 0), (1, 0), (0, -1), (-2, 0), (2, 0), (0, -2)];
+
+fn cells_fit<'a, I>(cells: I, map: &Array2D<Cell>, config: &GameConfig) -> bool
+where
+    I: IntoIterator<Item = &'a Vec2<i32>>,
+{
+    cells.into_iter().all(|cell| {
+        cell.x >= 0 && cell.x < config.map_width as i32 &&
+        cell.y >= 0 && cell.y < config.map_height as i32 &&
+        map[(cell.x as usize, cell.y as usize)].is_empty()
+    })
+}
+
+#[derive(PartialEq, Clone)]
+pub struct FallingBlocks {
+    pub body: Vec<Vec2<usize>>,
+    // The piece each block of `body` belongs to. Pieces fall on their own,
+    // so a rigid drop has a single piece.
+    piece: Vec<usize>,
+    pub length: usize,
+    pub last_move: u64,
+    pub hit_ground: bool,
+    // The fruit that was eaten to drop these, if any.
+    pub fruit: Option<FruitKind>,
+    // The color they keep once they land.
+    pub color: u8,
+}
+
+impl FallingBlocks {
+    fn from_snake(snake: &Snake, fruit: Option<FruitKind>, tick: u64,
+                  config: &GameConfig) -> FallingBlocks {
+        // A freshly spawned snake has several segments on one cell.
+        let mut body: Vec<Vec2<usize>> = Vec::with_capacity(snake.body.len());
+        for part in snake.body.iter() {
+            if !body.contains(part) {
+                body.push(*part);
+            }
+        }
+        let mut blocks = FallingBlocks {
+            length: snake.length,
+            body,
+            piece: Vec::new(),
+            last_move: tick,
+            hit_ground: false,
+            fruit,
+            color: 0,
+        };
+        blocks.split(config.drop_mode);
+        blocks
+    }
+
+    // A snake that went through a wrapping edge turns into blocks on both
+    // sides of the board. Each run of segments that touch each other is kept
+    // as its own piece, so the parts do not hang off each other.
+    fn split(&mut self, mode: DropMode) {
+        self.piece = match mode {
+            DropMode::Rigid    => {
+                let mut run = 0;
+                let mut pieces = Vec::with_capacity(self.body.len());
+                for (i, part) in self.body.iter().enumerate() {
+                    if i > 0 {
+                        let previous = self.body[i - 1];
+                        if part.x.abs_diff(previous.x) + part.y.abs_diff(previous.y) != 1 {
+                            run += 1;
+                        }
+                    }
+                    pieces.push(run);
+                }
+                pieces
+            }
+            DropMode::Columns  => self.body.iter().map(|part| part.x).collect(),
+            DropMode::Segments => (0..self.body.len()).collect(),
+        };
+    }
+
+    fn update(&mut self, map: &Array2D<Cell>, config: &GameConfig) {
+        if !self.fall(map, config) {
+            self.hit_ground = true;
+        }
+    }
+
+    // Moves every piece that has room one row down, lowest pieces first so
+    // the ones resting on them can follow in the same step. Returns whether
+    // anything moved.
+    fn fall(&mut self, map: &Array2D<Cell>, config: &GameConfig) -> bool {
+        let mut pieces = self.piece.clone();
+        pieces.sort_unstable();
+        pieces.dedup();
+        pieces.sort_by_key(|piece| Reverse(self.body.iter().zip(self.piece.iter())
+            .filter(|(_, p)| *p == piece)
+            .map(|(part, _)| part.y)
+            .max()));
+
+        let mut moved = false;
+        for piece in pieces {
+            let can_fall = self.body.iter().zip(self.piece.iter())
+                .filter(|(_, p)| **p == piece)
+                .all(|(part, _)| {
+                    let below = Vec2::new(part.x, part.y + 1);
+                    below.y < config.map_height && map[below.into_tuple()].is_empty() &&
+                    !self.body.iter().zip(self.piece.iter())
+                        .any(|(other, p)| *p != piece && *other == below)
+                });
+            if can_fall {
+                for (part, p) in self.body.iter_mut().zip(self.piece.iter()) {
+                    if *p == piece {
+                        part.y += 1;
+                    }
+                }
+                moved = true;
+            }
+        }
+        moved
+    }
+
+    // Whether every block would still be on the board and off the stack
+    // after moving by `offset`.
+    fn fits(&self, offset: Vec2<i32>, map: &Array2D<Cell>, config: &GameConfig) -> bool {
+        let moved: Vec<Vec2<i32>> = self.body.iter()
+            .map(|part| part.as_::<i32>() + offset)
+            .collect();
+        cells_fit(&moved, map, config)
+    }
+
+    // Where the blocks will come to rest if nothing moves them sideways.
+    pub fn ghost(&self, map: &Array2D<Cell>, config: &GameConfig) -> Vec<Vec2<usize>> {
+        let mut ghost = self.clone();
+        while ghost.fall(map, config) {}
+        ghost.body
+    }
+
+    fn try_move(&mut self, offset: Vec2<i32>, map: &Array2D<Cell>, config: &GameConfig) -> bool {
+        if !self.fits(offset, map, config) {
+            return false;
+        }
+        for part in self.body.iter_mut() {
+            *part = (part.as_::<i32>() + offset).as_();
+        }
+        true
+    }
+
+    // The pivot in doubled coordinates, so it can sit on a cell corner as
+    // well as a cell centre. Both coordinates always have the same parity,
+    // which is what keeps rotated cells on the grid.
+    fn pivot(&self, pivot: RotationPivot) -> Vec2<i32> {
+        match pivot {
+            RotationPivot::Head => self.body[0].as_::<i32>() * 2,
+            RotationPivot::Centroid => {
+                let sum = self.body.iter()
+                    .fold(Vec2::new(0, 0), |sum, part| sum + part.as_::<i32>());
+                let count = self.body.len() as f32;
+                let x = (sum.x as f32 * 2.0 / count).round() as i32;
+                let mut y = (sum.y as f32 * 2.0 / count).round() as i32;
+                if (x - y) % 2 != 0 {
+                    y += 1;
+                }
+                Vec2::new(x, y)
+            }
+        }
+    }
+
+    // Turns the shape a quarter turn. If it no longer fits it is first
+    // pushed back inside the walls, then nudged by each of the KICKS until
+    // it fits the stack; if nothing fits the rotation does not happen.
+    fn rotate(&mut self, clockwise: bool, map: &Array2D<Cell>, config: &GameConfig) -> bool {
+        let pivot = self.pivot(config.rotation_pivot);
+        let rotated: Vec<Vec2<i32>> = self.body.iter()
+            .map(|part| {
+                let offset = part.as_::<i32>() * 2 - pivot;
+                let turned = if clockwise {
+                    Vec2::new(-offset.y, offset.x)
+                } else {
+                    Vec2::new(offset.y, -offset.x)
+                };
+                (pivot + turned) / 2
+            })
+            .collect();
+
+        let min_x = rotated.iter().map(|cell| cell.x).min().unwrap();
+        let max_x = rotated.iter().map(|cell| cell.x).max().unwrap();
+        let min_y = rotated.iter().map(|cell| cell.y).min().unwrap();
+        let max_y = rotated.iter().map(|cell| cell.y).max().unwrap();
+        let mut base = Vec2::new(0, 0);
+        if min_x < 0 {
+            base.x = -min_x;
+        } else if max_x >= config.map_width as i32 {
+            base.x = config.map_width as i32 - 1 - max_x;
+        }
+        if min_y < 0 {
+            base.y = -min_y;
+        } else if max_y >= config.map_height as i32 {
+            base.y = config.map_height as i32 - 1 - max_y;
+        }
+
+        for (x, y) in KICKS.iter() {
+            let offset = base + Vec2::new(*x, *y);
+            let kicked: Vec<Vec2<i32>> = rotated.iter().map(|cell| *cell + offset).collect();
+            if cells_fit(&kicked, map, config) {
+                self.body = kicked.iter().map(|cell| cell.as_()).collect();
+                self.split(config.drop_mode);
+                return true;
+            }
+        }
+        false
+    }
+
+    // Applies a player input, returning whether it did anything.
+    fn handle_input(&mut self, input: Input, map: &Array2D<Cell>, config: &GameConfig,
+                    tick: u64) -> bool {
+        match input {
+            Input::MoveLeft  => self.try_move(Vec2::new(-1, 0), map, config),
+            Input::MoveRight => self.try_move(Vec2::new(1, 0), map, config),
+            Input::SoftDrop  => {
+                if self.fall(map, config) {
+                    self.last_move = tick;
+                } else {
+                    self.hit_ground = true;
+                }
+                true
+            }
+            Input::HardDrop  => {
+                while self.fall(map, config) {}
+                self.hit_ground = true;
+                true
+            }
+            Input::RotateClockwise        => self.rotate(true, map, config),
+            Input::RotateCounterClockwise => self.rotate(false, map, config),
+            Input::Turn(_) | Input::Detach => false,
+        }
+    }
+}
+
+// How long a slow-motion fruit keeps the snake slowed down after it lands,
+// and by how much.
+const SLOW_MOTION_MS: u64 = 5000;
+const SLOW_MOTION_FACTOR: u64 = 2;
+
+// Segments a shrinking fruit takes off the snake.
+const SHRINK_LENGTH: usize = 2;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum FruitKind {
+    Normal,
+    // Worth extra points.
+    Golden,
+    // Takes segments off the next snake.
+    Shrinking,
+    // Blows a 3x3 hole in the stack where the head lands.
+    Bomb,
+    // Slows the next snake down for a while.
+    SlowMotion,
+}
+
+impl FruitKind {
+    pub const ALL: [FruitKind; 5] = [
+        FruitKind::Normal,
+        FruitKind::Golden,
+        FruitKind::Shrinking,
+        FruitKind::Bomb,
+        FruitKind::SlowMotion,
+    ];
+
+    fn weight(self, config: &GameConfig) -> u32 {
+        match self {
+            FruitKind::Normal     => config.fruit_weight_normal,
+            FruitKind::Golden     => config.fruit_weight_golden,
+            FruitKind::Shrinking  => config.fruit_weight_shrinking,
+            FruitKind::Bomb       => config.fruit_weight_bomb,
+            FruitKind::SlowMotion => config.fruit_weight_slow_motion,
+        }
+    }
+
+    fn random(config: &GameConfig, rng: &Rng) -> FruitKind {
+        let total: u32 = FruitKind::ALL.iter().map(|kind| kind.weight(config)).sum();
+        let mut roll = rng.u32(0..total);
+        for kind in FruitKind::ALL {
+            if roll < kind.weight(config) {
+                return kind;
+            }
+            roll -= kind.weight(config);
+        }
+        FruitKind::Normal
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub struct Fruit {
+    pub pos: Vec2<usize>,
+    pub kind: FruitKind,
+}
+
+impl Fruit {
+    fn new(pos: Vec2<usize>, kind: FruitKind) -> Fruit {
+        Fruit {
+            pos,
+            kind,
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum GameOverReason {
+    Wall,
+    SelfCollision,
+    Stack,
+    TopOut,
+    BoardFull,
+    Goal,
+}
+
+impl GameOverReason {
+    pub fn description(self) -> &'static str {
+        match self {
+            GameOverReason::Wall          => "You crashed into the wall",
+            GameOverReason::SelfCollision => "You bit yourself",
+            GameOverReason::Stack         => "You crashed into the stack",
+            GameOverReason::TopOut        => "No room left for a new snake",
+            GameOverReason::BoardFull     => "The board is full. You win!",
+            GameOverReason::Goal          => "Level complete. You win!",
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum PlayState {
+    Normal,
+    Falling,
+    GameOver(GameOverReason),
+}
+
+// All game rules live here. The simulation never looks at the clock or the
+// keyboard: it only advances when `step` is called, so it can be driven by
+// the tetra adapter, a bot or a test at whatever pace suits them.
+pub struct Simulation {
+    pub snake: Snake,
+    pub map: Array2D<Cell>,
+    pub fruits: Vec<Fruit>,
+    pub falling_blocks: Option<FallingBlocks>,
+    pub state: PlayState,
+    pub tick: u64,
+    pub seed: u64,
+    pub replay: Replay,
+    pub config: GameConfig,
+    pub score: Score,
+    // The tick the player last dropped the snake without eating a fruit.
+    last_detach: Option<u64>,
+    // The tick a slow-motion fruit stops slowing the snake down.
+    slow_until: u64,
+    // How many snakes have been dropped.
+    generation: u32,
+    rng: Rng,
+}
+
+impl Simulation {
+    pub fn new(config: GameConfig, seed: u64) -> Simulation {
+        let rng = Rng::with_seed(seed);
+        let mut map = Array2D::filled_with(
+            Cell::Empty, config.map_width, config.map_height);
+        let mut snake = Snake::new(Vec2::new(3, 1), Direction::Right);
+        let mut fruits = Vec::new();
+        if let Some(level) = &config.level {
+            for cell in level.blocks.iter() {
+                map[cell.into_tuple()] = Cell::Block { color: 0, origin_length: 0, placed_at: 0 };
+            }
+            for cell in level.walls.iter() {
+                map[cell.into_tuple()] = Cell::Wall;
+            }
+            let (start, direction) = level.snake;
+            snake = Snake::new(start, direction);
+            for cell in level.fruits.iter() {
+                fruits.push(Fruit::new(*cell, FruitKind::random(&config, &rng)));
+            }
+        }
+
+        let mut simulation = Simulation {
+            snake,
+            map,
+            fruits,
+            falling_blocks: None,
+            state: PlayState::Normal,
+            tick: 0,
+            seed,
+            replay: Replay::new(&config, seed),
+            config,
+            score: Score::default(),
+            last_detach: None,
+            slow_until: 0,
+            generation: 0,
+            rng,
+        };
+        simulation.fill_fruits();
+        simulation
+    }
+
+    pub fn step(&mut self, inputs: &[Input]) {
+        match self.state {
+            PlayState::Normal  => self.step_normal(inputs),
+            PlayState::Falling => self.step_falling(inputs),
+            PlayState::GameOver(_) => return,
+        }
+        self.tick += 1;
+    }
+
+    // Where the falling blocks will land.
+    pub fn ghost(&self) -> Option<Vec<Vec2<usize>>> {
+        self.falling_blocks.as_ref()
+            .map(|falling_blocks| falling_blocks.ghost(&self.map, &self.config))
+    }
+
+    // Where the snake would land if it ate a fruit right now.
+    pub fn landing_preview(&self) -> Option<Vec<Vec2<usize>>> {
+        if self.state != PlayState::Normal || self.snake.disabled {
+            return None;
+        }
+        let blocks = FallingBlocks::from_snake(&self.snake, None, self.tick, &self.config);
+        Some(blocks.ghost(&self.map, &self.config))
+    }
+
+    pub fn level(&self) -> u32 {
+        1 + self.score.lines / self.config.lines_per_level
+    }
+
+    // Ticks between snake moves at the current level.
+    fn move_interval(&self) -> u64 {
+        let interval = self.level_interval(self.config.speed);
+        if self.tick < self.slow_until {
+            interval * SLOW_MOTION_FACTOR
+        } else {
+            interval
+        }
+    }
+
+    // Ticks between falling block moves at the current level.
+    fn fall_interval(&self) -> u64 {
+        self.level_interval(self.config.fall_speed)
+    }
+
+    fn level_interval(&self, base: u64) -> u64 {
+        let factor = self.config.speed_curve.powi(self.level() as i32 - 1);
+        let ms = (base as f64 * factor) as u64;
+        ms_to_ticks(ms.max(self.config.min_speed)).max(1)
+    }
+
+    fn game_over(&mut self, reason: GameOverReason) {
+        self.state = PlayState::GameOver(reason);
+        self.replay.score = Some(self.score.clone());
+    }
+
+    // Rows whose cells are all blocks, apart from any walls, which neither
+    // fill a row nor leave a gap in it.
+    fn full_rows(&self) -> Vec<usize> {
+        (0..self.config.map_height)
+            .filter(|y| {
+                let mut row = (0..self.config.map_width)
+                    .map(|x| self.map[(x, *y)])
+                    .filter(|cell| *cell != Cell::Wall)
+                    .peekable();
+                row.peek().is_some() && row.all(Cell::is_block)
+            })
+            .collect()
+    }
+
+    // Clears full rows, and under the color-match ruleset big enough groups
+    // of same-colored blocks as well. With naive gravity the blocks above a
+    // cleared cell move down by one; with cascade gravity each connected
+    // lump of blocks falls on its own. Either can make more to clear and set
+    // off a chain.
+    //
+    // Fruits move down with the blocks either way. This only happens while
+    // blocks land, when the snake is off the board, so the snake is never
+    // moved: it respawns on whatever the clear left behind.
+    fn clear(&mut self) {
+        let mut chain = 0;
+        loop {
+            let rows = self.full_rows();
+            let groups = match self.config.ruleset {
+                Ruleset::Rows       => Vec::new(),
+                Ruleset::ColorMatch => self.color_matches(),
+            };
+            if rows.is_empty() && groups.is_empty() {
+                break;
+            }
+            chain += 1;
+
+            let mut cells: Vec<Vec2<usize>> = rows.iter()
+                .flat_map(|y| (0..self.config.map_width).map(move |x| Vec2::new(x, *y)))
+                .filter(|cell| self.map[cell.into_tuple()].is_block())
+                .chain(groups.iter().flatten().copied())
+                .collect();
+            cells.sort_unstable_by_key(|cell| (cell.y, cell.x));
+            cells.dedup();
+
+            for fruit in self.fruits.iter_mut() {
+                let map = &self.map;
+                fruit.pos.y += cells.iter()
+                    .filter(|cell| cell.x == fruit.pos.x && cell.y > fruit.pos.y &&
+                                   (fruit.pos.y..cell.y).all(|y| map[(cell.x, y)] != Cell::Wall))
+                    .count();
+            }
+            match self.config.gravity {
+                Gravity::Naive   => self.remove_cells(&cells),
+                Gravity::Cascade => {
+                    for cell in cells.iter() {
+                        self.map[cell.into_tuple()] = Cell::Empty;
+                    }
+                    self.settle();
+                }
+            }
+            self.score.rows_cleared(rows.len(), chain);
+            self.score.groups_cleared(groups.iter().map(|group| group.len()).sum(), chain);
+        }
+        if chain > 0 && !self.map.elements_row_major_iter().any(|cell| cell.is_block()) {
+            self.score.board_cleared();
+        }
+    }
+
+    // Moves the blocks above each cleared cell down by one, going from the
+    // top of the board down. A wall holds up whatever is above it, so only
+    // the blocks between the cell and the nearest wall above it move.
+    fn remove_cells(&mut self, cells: &[Vec2<usize>]) {
+        for cell in cells.iter() {
+            let mut y = cell.y;
+            while y > 0 && self.map[(cell.x, y - 1)] != Cell::Wall {
+                self.map[(cell.x, y)] = self.map[(cell.x, y - 1)];
+                y -= 1;
+            }
+            self.map[(cell.x, y)] = Cell::Empty;
+        }
+    }
+
+    // Groups of at least `match_size` orthogonally connected blocks of one
+    // color. A snake lands in a single color, so a group has to be made up
+    // of more than one of them; the blocks a level starts with never match.
+    fn color_matches(&self) -> Vec<Vec<Vec2<usize>>> {
+        let same_color = |a: Cell, b: Cell| match (a, b) {
+            (Cell::Block { color: a, .. }, Cell::Block { color: b, .. }) => a == b && a != 0,
+            _ => false,
+        };
+        let landed_at = |cell: &Vec2<usize>| match self.map[cell.into_tuple()] {
+            Cell::Block { placed_at, .. } => Some(placed_at),
+            _ => None,
+        };
+        self.groups(same_color).into_iter()
+            .filter(|group| group.len() >= self.config.match_size &&
+                            group.iter().any(|cell| landed_at(cell) != landed_at(&group[0])))
+            .collect()
+    }
+
+    // Groups the blocks in `map` into orthogonally connected lumps.
+    fn connected_groups(&self) -> Vec<Vec<Vec2<usize>>> {
+        self.groups(|_, _| true)
+    }
+
+    // Groups the non-empty cells of `map` into orthogonally connected lumps
+    // of cells that `joined` says belong together.
+    fn groups<F: Fn(Cell, Cell) -> bool>(&self, joined: F) -> Vec<Vec<Vec2<usize>>> {
+        let (width, height) = (self.config.map_width, self.config.map_height);
+        let mut seen = Array2D::filled_with(false, width, height);
+        let mut groups = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                if self.map[(x, y)].is_empty() || seen[(x, y)] {
+                    continue;
+                }
+                let mut group = Vec::new();
+                let mut stack = vec!(Vec2::new(x, y));
+                seen[(x, y)] = true;
+                while let Some(cell) = stack.pop() {
+                    group.push(cell);
+                    for next in neighbours(cell, width, height) {
+                        let (here, there) = (self.map[cell.into_tuple()], self.map[next.into_tuple()]);
+                        if !there.is_empty() && !seen[next.into_tuple()] && joined(here, there) {
+                            seen[next.into_tuple()] = true;
+                            stack.push(next);
+                        }
+                    }
+                }
+                groups.push(group);
+            }
+        }
+        groups
+    }
+
+    // Drops every lump of blocks one row at a time, lowest lumps first,
+    // until none of them can fall any further. Lumps stuck to a wall stay
+    // where they are.
+    fn settle(&mut self) {
+        loop {
+            let mut groups = self.connected_groups();
+            groups.sort_by_key(|group| Reverse(group.iter().map(|cell| cell.y).max()));
+            let mut moved = false;
+            for group in groups.iter() {
+                if group.iter().any(|cell| self.map[cell.into_tuple()] == Cell::Wall) {
+                    continue;
+                }
+                let can_fall = group.iter().all(|cell| {
+                    cell.y + 1 < self.config.map_height &&
+                    (self.map[(cell.x, cell.y + 1)].is_empty() ||
+                     group.contains(&Vec2::new(cell.x, cell.y + 1)))
+                });
+                if !can_fall {
+                    continue;
+                }
+                let cells: Vec<Cell> = group.iter().map(|cell| self.map[cell.into_tuple()]).collect();
+                for cell in group.iter() {
+                    self.map[cell.into_tuple()] = Cell::Empty;
+                }
+                for (cell, value) in group.iter().zip(cells) {
+                    self.map[(cell.x, cell.y + 1)] = value;
+                }
+                moved = true;
+            }
+            if !moved {
+                break;
+            }
+        }
+    }
+
+    fn step_normal(&mut self, inputs: &[Input]) {
+        if self.snake.disabled {
+            return;
+        }
+
+        let mut fruits_to_retain = Vec::new();
+        let mut eaten = None;
+        let head = *self.snake.body.first().unwrap();
+        for fruit in self.fruits.iter() {
+            if fruit.pos == head {
+                self.score.fruit_eaten(fruit.kind);
+                self.score.dropped(self.snake.length);
+                eaten = Some(fruit.kind);
+                fruits_to_retain.push(false);
+            } else {
+                fruits_to_retain.push(true);
+            }
+        }
+        let mut iter = fruits_to_retain.iter();
+        self.fruits.retain(|_| *iter.next().unwrap());
+        if let Some(kind) = eaten {
+            if self.config.fruit_respawn == FruitRespawn::OnEat {
+                self.fill_fruits();
+            }
+            self.drop_snake(Some(kind));
+            if kind == FruitKind::Shrinking {
+                self.snake.length = self.snake.length.saturating_sub(SHRINK_LENGTH).max(1);
+            }
+            return;
+        }
+
+        if self.snake.bites_itself() {
+            self.game_over(GameOverReason::SelfCollision);
+            return;
+        }
+        if !self.map[head.into_tuple()].is_empty() {
+            self.game_over(GameOverReason::Stack);
+            return;
+        }
+
+        for input in inputs {
+            let accepted = match *input {
+                Input::Turn(direction) => self.snake.queue_direction(direction),
+                Input::Detach => self.can_detach(),
+                _ => false,
+            };
+            if accepted {
+                self.replay.record(self.tick, *input);
+            }
+            if accepted && *input == Input::Detach {
+                self.detach();
+                return;
+            }
+        }
+
+        if self.tick - self.snake.last_move >= self.move_interval() {
+            if self.snake.direction_queue.len() > 1 {
+                self.snake.direction_queue.remove(0);
+            }
+            self.snake.direction_queue.truncate(2);
+            if !self.snake.update(&self.config) {
+                self.game_over(GameOverReason::Wall);
+                return;
+            }
+            self.snake.last_move = self.tick;
+        }
+    }
+
+    // Whether the snake could start at `cell` heading in `direction` and
+    // still have `spawn_runway` free cells in front of it.
+    fn has_runway(&self, cell: Vec2<usize>, direction: Direction) -> bool {
+        let step = direction.offset();
+        let runway = match i32::try_from(self.config.spawn_runway) {
+            Ok(runway) => runway,
+            Err(_) => return false,
+        };
+        (1..=runway).all(|distance| {
+            match wrap_cell(cell.as_::<i32>() + step * distance, &self.config) {
+                Some(ahead) => self.map[ahead.into_tuple()].is_empty(),
+                None => false,
+            }
+        })
+    }
+
+    // Picks where a new snake starts: the level's start if it is free and
+    // has room, otherwise the free cell closest to the top middle of the
+    // board that leaves it room to move, heading down if it can. Only cells
+    // that can be reached from the top row count, so a hole buried in the
+    // stack is never used. None means the stack has no room left for a
+    // snake.
+    fn find_spawn(&self) -> Option<(Vec2<usize>, Direction)> {
+        if let Some((start, direction)) = self.config.level.as_ref().map(|level| level.snake) {
+            if self.map[start.into_tuple()].is_empty() &&
+               !self.fruits.iter().any(|fruit| fruit.pos == start) &&
+               self.has_runway(start, direction) {
+                return Some((start, direction));
+            }
+        }
+        let width = self.config.map_width;
+        let middle = width / 2;
+        let mut columns: Vec<usize> = (0..width).collect();
+        columns.sort_by_key(|x| (*x as i32 - middle as i32).abs());
+
+        let top: Vec<Vec2<usize>> = (0..width).map(|x| Vec2::new(x, 0)).collect();
+        let mut reachable = Array2D::filled_with(false, width, self.config.map_height);
+        for (cell, _) in spawner::reachable_cells(&self.map, &top, &self.config) {
+            reachable[cell.into_tuple()] = true;
+        }
+
+        for y in 0..self.config.map_height {
+            for x in columns.iter() {
+                let cell = Vec2::new(*x, y);
+                if !reachable[cell.into_tuple()] ||
+                   self.fruits.iter().any(|fruit| fruit.pos == cell) {
+                    continue;
+                }
+                let sideways = if *x < middle {
+                    [Direction::Right, Direction::Left]
+                } else {
+                    [Direction::Left, Direction::Right]
+                };
+                let directions = [Direction::Down, sideways[0], sideways[1], Direction::Up];
+                if let Some(direction) = directions.iter()
+                    .find(|direction| self.has_runway(cell, **direction)) {
+                    return Some((cell, *direction));
+                }
+            }
+        }
+        None
+    }
+
+    // Puts a fruit on a free cell the snake can reach. Returns false if
+    // there is no such cell left.
+    fn spawn_fruit(&mut self) -> bool {
+        let candidates: Vec<(Vec2<usize>, usize)> =
+            spawner::reachable_cells(&self.map, &self.snake.body[..1], &self.config).into_iter()
+                .filter(|(cell, _)| !self.snake.body.contains(cell) &&
+                                    !self.fruits.iter().any(|fruit| fruit.pos == *cell))
+                .collect();
+        match spawner::pick(&candidates, &self.map, &self.config, &self.rng) {
+            Some(cell) => {
+                let kind = FruitKind::random(&self.config, &self.rng);
+                self.fruits.push(Fruit::new(cell, kind));
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Spawns fruits until there are as many as the config asks for, or
+    // until there is no room left for another.
+    fn fill_fruits(&mut self) {
+        while self.fruits.len() < self.config.fruit_count {
+            if !self.spawn_fruit() {
+                break;
+            }
+        }
+    }
+
+    fn drop_snake(&mut self, fruit: Option<FruitKind>) {
+        let mut falling_blocks = FallingBlocks::from_snake(&self.snake, fruit, self.tick, &self.config);
+        falling_blocks.color = self.block_color(fruit);
+        self.falling_blocks = Some(falling_blocks);
+        self.generation += 1;
+        self.snake.disabled = true;
+        self.state = PlayState::Falling;
+    }
+
+    fn block_color(&self, fruit: Option<FruitKind>) -> u8 {
+        let index = match self.config.block_colors {
+            BlockColors::Generation => self.generation as usize,
+            BlockColors::Length     => self.snake.length.saturating_sub(1),
+            BlockColors::Fruit      => match fruit {
+                Some(kind) => FruitKind::ALL.iter().position(|other| *other == kind).unwrap(),
+                None => FruitKind::ALL.len(),
+            },
+        };
+        1 + (index % BLOCK_COLORS as usize) as u8
+    }
+
+    fn can_detach(&self) -> bool {
+        let cooldown = ms_to_ticks(self.config.detach_cooldown);
+        !matches!(self.last_detach, Some(tick) if self.tick - tick < cooldown)
+    }
+
+    // Drops the snake where it is without a fruit, at the price set in
+    // the config.
+    fn detach(&mut self) {
+        self.last_detach = Some(self.tick);
+        self.score.detached(self.config.detach_cost, self.snake.length);
+        self.drop_snake(None);
+        let length = self.snake.length.saturating_sub(self.config.detach_length_loss);
+        self.snake.length = length.max(1);
+    }
+
+    fn step_falling(&mut self, inputs: &[Input]) {
+        let fall_interval = self.fall_interval();
+        let mut fallen = false;
+        if let Some(falling_blocks) = &mut self.falling_blocks {
+            if falling_blocks.hit_ground {
+                fallen = true;
+            }
+
+            for input in inputs {
+                if falling_blocks.hit_ground {
+                    break;
+                }
+                if falling_blocks.handle_input(*input, &self.map, &self.config, self.tick) {
+                    self.replay.record(self.tick, *input);
+                }
+            }
+
+            if !falling_blocks.hit_ground && self.tick - falling_blocks.last_move >= fall_interval {
+                falling_blocks.update(&self.map, &self.config);
+                falling_blocks.last_move = self.tick;
+            }
+        }
+        if fallen {
+            self.blocks_to_map();
+            if let Some(falling_blocks) = self.falling_blocks.take() {
+                match falling_blocks.fruit {
+                    Some(FruitKind::Bomb) => self.explode(falling_blocks.body[0]),
+                    Some(FruitKind::SlowMotion) =>
+                        self.slow_until = self.tick + ms_to_ticks(SLOW_MOTION_MS),
+                    _ => {}
+                }
+            }
+            self.clear();
+            if self.config.goal > 0 && self.score.lines >= self.config.goal {
+                self.game_over(GameOverReason::Goal);
+                return;
+            }
+            let (spawn, direction) = match self.find_spawn() {
+                Some(spawn) => spawn,
+                None => {
+                    self.game_over(GameOverReason::TopOut);
+                    return;
+                }
+            };
+            self.snake.disabled = false;
+            self.snake.length += 1;
+            // The body is rebuilt rather than refilled, since detaching or a
+            // shrinking fruit can have made the snake shorter.
+            self.snake.body = vec![spawn; self.snake.length];
+            self.snake.direction_queue = vec!(direction);
+            self.state = PlayState::Normal;
+
+            // Fruits that the stack has fallen onto are moved somewhere
+            // else, along with any that were eaten.
+            let map = &self.map;
+            self.fruits.retain(|fruit| map[fruit.pos.into_tuple()].is_empty());
+            self.fill_fruits();
+            if self.fruits.is_empty() {
+                self.game_over(GameOverReason::BoardFull);
+            }
+        }
+    }
+
+    // Clears the blocks around `center`, the landed head of a snake that
+    // ate a bomb. Walls survive it.
+    fn explode(&mut self, center: Vec2<usize>) {
+        let xs = center.x.saturating_sub(1)..(center.x + 2).min(self.config.map_width);
+        for x in xs {
+            let ys = center.y.saturating_sub(1)..(center.y + 2).min(self.config.map_height);
+            for y in ys {
+                if self.map[(x, y)].is_block() {
+                    self.map[(x, y)] = Cell::Empty;
+                }
+            }
+        }
+    }
+
+    fn blocks_to_map(&mut self) {
+        if let Some(falling_blocks) = &self.falling_blocks {
+            for block in falling_blocks.body.iter() {
+                self.map[(block.x, block.y)] = Cell::Block {
+                    color: falling_blocks.color,
+                    origin_length: falling_blocks.length,
+                    placed_at: self.tick,
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Wrap;
+
+    fn block(color: u8, placed_at: u64) -> Cell {
+        Cell::Block { color, origin_length: 3, placed_at }
+    }
+
+    fn falling(cells: &[(usize, usize)], config: &GameConfig) -> FallingBlocks {
+        let mut blocks = FallingBlocks {
+            body: cells.iter().map(|(x, y)| Vec2::new(*x, *y)).collect(),
+            piece: Vec::new(),
+            length: cells.len(),
+            last_move: 0,
+            hit_ground: false,
+            fruit: None,
+            color: 1,
+        };
+        blocks.split(config.drop_mode);
+        blocks
+    }
+
+    // Hard drops `cells` as if a snake had just turned into them, and steps
+    // until they have landed.
+    fn land(simulation: &mut Simulation, cells: &[(usize, usize)]) {
+        let mut blocks = falling(cells, &simulation.config);
+        blocks.last_move = simulation.tick;
+        simulation.falling_blocks = Some(blocks);
+        simulation.snake.disabled = true;
+        simulation.state = PlayState::Falling;
+        simulation.step(&[Input::HardDrop]);
+        simulation.step(&[]);
+    }
+
+    // Heads for the first fruit and drops the blocks with a few moves
+    // thrown in.
+    fn bot(simulation: &Simulation) -> Vec<Input> {
+        match simulation.state {
+            PlayState::Normal => {
+                let head = simulation.snake.body[0];
+                let target = simulation.fruits[0].pos;
+                let direction = if target.x < head.x {
+                    Direction::Left
+                } else if target.x > head.x {
+                    Direction::Right
+                } else if target.y < head.y {
+                    Direction::Up
+                } else {
+                    Direction::Down
+                };
+                vec!(Input::Turn(direction))
+            }
+            PlayState::Falling => match simulation.tick % 5 {
+                0 => vec!(Input::RotateClockwise),
+                1 => vec!(Input::MoveRight),
+                2 => vec!(Input::HardDrop),
+                _ => vec!(),
+            },
+            PlayState::GameOver(_) => vec!(),
+        }
+    }
+
+    #[test]
+    fn same_seed_and_inputs_play_the_same_game() {
+        let config = GameConfig::default();
+        let mut played = Simulation::new(config.clone(), 42);
+        for _ in 0..5000 {
+            let inputs = bot(&played);
+            played.step(&inputs);
+        }
+        assert!(played.score.fruits > 0);
+
+        let mut replayed = Simulation::new(config, 42);
+        for _ in 0..5000 {
+            let inputs = played.replay.inputs_at(replayed.tick);
+            replayed.step(&inputs);
+        }
+        assert_eq!(replayed.tick, played.tick);
+        assert_eq!(replayed.state, played.state);
+        assert_eq!(replayed.score, played.score);
+        assert_eq!(replayed.map, played.map);
+        assert_eq!(replayed.snake.body, played.snake.body);
+        assert_eq!(replayed.fruits, played.fruits);
+        assert_eq!(replayed.replay.inputs, played.replay.inputs);
+    }
+
+    #[test]
+    fn huge_intervals_wait_instead_of_overflowing() {
+        let config = GameConfig {
+            speed: u64::MAX / 10,
+            fall_speed: u64::MAX,
+            min_speed: u64::MAX / 10,
+            detach_cooldown: u64::MAX,
+            ..GameConfig::default()
+        };
+        assert!(config.validate().is_ok());
+        let mut simulation = Simulation::new(config, 1);
+        let head = simulation.snake.body[0];
+        for _ in 0..10 {
+            simulation.step(&[]);
+        }
+        assert_eq!(simulation.snake.body[0], head);
+        simulation.step(&[Input::Detach]);
+        assert_eq!(simulation.state, PlayState::Falling);
+    }
+
+    #[test]
+    fn rotation_is_pushed_off_the_wall_and_kicked_off_the_stack() {
+        let config = GameConfig::default();
+        let mut map = Array2D::filled_with(Cell::Empty, 9, 9);
+
+        let mut blocks = falling(&[(8, 2), (8, 3), (8, 4)], &config);
+        assert!(blocks.rotate(false, &map, &config));
+        assert_eq!(blocks.body, vec!(Vec2::new(6, 2), Vec2::new(7, 2), Vec2::new(8, 2)));
+
+        map[(6, 2)] = block(1, 0);
+        let mut blocks = falling(&[(8, 2), (8, 3), (8, 4)], &config);
+        assert!(blocks.rotate(false, &map, &config));
+        assert_eq!(blocks.body, vec!(Vec2::new(6, 1), Vec2::new(7, 1), Vec2::new(8, 1)));
+    }
+
+    #[test]
+    fn cascade_gravity_chains_clears() {
+        let config = GameConfig {
+            map_width: 5,
+            map_height: 5,
+            gravity: Gravity::Cascade,
+            ..GameConfig::default()
+        };
+        let mut simulation = Simulation::new(config, 1);
+        simulation.map[(4, 1)] = block(1, 0);
+        for x in 0..5 {
+            simulation.map[(x, 2)] = block(1, 0);
+        }
+        for x in 0..4 {
+            simulation.map[(x, 4)] = block(1, 0);
+        }
+        simulation.clear();
+        assert_eq!(simulation.score.lines, 2);
+        assert_eq!(simulation.score.points, 100 + 100 * 2 + 1000);
+        assert!(simulation.map.elements_row_major_iter().all(|cell| cell.is_empty()));
+    }
+
+    #[test]
+    fn naive_gravity_moves_everything_down_a_row() {
+        let config = GameConfig {
+            map_width: 5,
+            map_height: 5,
+            ..GameConfig::default()
+        };
+        let mut simulation = Simulation::new(config, 1);
+        simulation.map[(4, 1)] = block(1, 0);
+        for x in 0..5 {
+            simulation.map[(x, 2)] = block(1, 0);
+        }
+        simulation.clear();
+        assert_eq!(simulation.score.lines, 1);
+        assert!(simulation.map[(4, 2)].is_block());
+        assert!(simulation.map[(4, 1)].is_empty());
+    }
+
+    #[test]
+    fn columns_drop_each_column_on_its_own() {
+        let config = GameConfig { drop_mode: DropMode::Columns, ..GameConfig::default() };
+        let mut simulation = Simulation::new(config, 1);
+        simulation.map[(0, 8)] = block(1, 0);
+        land(&mut simulation, &[(0, 2), (1, 2)]);
+        assert!(simulation.map[(0, 7)].is_block());
+        assert!(simulation.map[(1, 8)].is_block());
+        assert!(simulation.map[(1, 7)].is_empty());
+    }
+
+    #[test]
+    fn rigid_drop_stops_as_one_piece() {
+        let mut simulation = Simulation::new(GameConfig::default(), 1);
+        simulation.map[(0, 8)] = block(1, 0);
+        land(&mut simulation, &[(0, 2), (1, 2)]);
+        assert!(simulation.map[(0, 7)].is_block());
+        assert!(simulation.map[(1, 7)].is_block());
+        assert!(simulation.map[(1, 8)].is_empty());
+    }
+
+    #[test]
+    fn snake_spawns_at_the_top_middle_heading_down() {
+        let simulation = Simulation::new(GameConfig::default(), 1);
+        assert_eq!(simulation.find_spawn(), Some((Vec2::new(4, 0), Direction::Down)));
+    }
+
+    #[test]
+    fn buried_holes_are_not_spawn_points() {
+        let mut simulation = Simulation::new(GameConfig::default(), 1);
+        for y in 0..6 {
+            for x in 0..9 {
+                simulation.map[(x, y)] = Cell::Wall;
+            }
+        }
+        assert_eq!(simulation.find_spawn(), None);
+
+        land(&mut simulation, &[(0, 8)]);
+        assert_eq!(simulation.state, PlayState::GameOver(GameOverReason::TopOut));
+        assert!(simulation.snake.disabled);
+    }
+
+    #[test]
+    fn runways_too_long_for_an_i32_never_fit() {
+        let config = GameConfig { spawn_runway: 1 << 32, ..GameConfig::default() };
+        let simulation = Simulation::new(config, 1);
+        assert_eq!(simulation.find_spawn(), None);
+    }
+
+    #[test]
+    fn fruits_only_spawn_where_the_snake_can_reach() {
+        let mut simulation = Simulation::new(GameConfig::default(), 1);
+        for y in 0..9 {
+            simulation.map[(5, y)] = Cell::Wall;
+        }
+        for _ in 0..50 {
+            simulation.fruits.clear();
+            assert!(simulation.spawn_fruit());
+            assert!(simulation.fruits[0].pos.x < 5);
+        }
+    }
+
+    #[test]
+    fn color_match_clears_groups_made_of_several_snakes() {
+        let config = GameConfig { ruleset: Ruleset::ColorMatch, ..GameConfig::default() };
+        let mut simulation = Simulation::new(config, 1);
+        for x in 0..2 {
+            simulation.map[(x, 8)] = block(2, 1);
+        }
+        for x in 2..4 {
+            simulation.map[(x, 8)] = block(2, 2);
+        }
+        for x in 5..9 {
+            simulation.map[(x, 8)] = block(3, 3);
+        }
+        simulation.clear();
+        assert!((0..4).all(|x| simulation.map[(x, 8)].is_empty()));
+        assert!((5..9).all(|x| simulation.map[(x, 8)].is_block()));
+        assert_eq!(simulation.score.points, 4 * 20);
+    }
+
+    #[test]
+    fn shrinking_fruit_shortens_the_next_snake() {
+        let mut simulation = Simulation::new(GameConfig::default(), 1);
+        simulation.snake.length = 6;
+        simulation.snake.body = (1..7).rev().map(|x| Vec2::new(x, 4)).collect();
+        let head = simulation.snake.body[0];
+        simulation.fruits = vec!(Fruit::new(head, FruitKind::Shrinking));
+        simulation.step(&[]);
+        assert_eq!(simulation.state, PlayState::Falling);
+        simulation.step(&[Input::HardDrop]);
+        simulation.step(&[]);
+        assert_eq!(simulation.state, PlayState::Normal);
+        assert_eq!(simulation.snake.length, 6 - SHRINK_LENGTH + 1);
+        assert_eq!(simulation.snake.body.len(), simulation.snake.length);
+        for _ in 0..100 {
+            simulation.step(&[]);
+        }
+        assert_eq!(simulation.state, PlayState::Normal);
+        assert_eq!(simulation.snake.body.len(), simulation.snake.length);
+    }
+
+    #[test]
+    fn detaching_shortens_the_next_snake() {
+        let config = GameConfig { detach_length_loss: 3, ..GameConfig::default() };
+        let mut simulation = Simulation::new(config, 1);
+        simulation.fruits.clear();
+        simulation.snake.length = 6;
+        simulation.snake.body = (1..7).rev().map(|x| Vec2::new(x, 4)).collect();
+        simulation.step(&[Input::Detach]);
+        assert_eq!(simulation.state, PlayState::Falling);
+        assert_eq!(simulation.score.max_length, 6);
+        simulation.step(&[Input::HardDrop]);
+        simulation.step(&[]);
+        assert_eq!(simulation.snake.length, 6 - 3 + 1);
+        assert_eq!(simulation.snake.body.len(), simulation.snake.length);
+    }
+
+    #[test]
+    fn snake_moves_cross_only_the_wrapping_edges() {
+        let corner = Vec2::new(0, 0);
+        let config = GameConfig::default();
+        assert_eq!(snake_moves(corner, &config), vec!(Vec2::new(0, 1), Vec2::new(1, 0)));
+        let config = GameConfig { wrap: Wrap::Horizontal, ..config };
+        assert_eq!(snake_moves(corner, &config),
+                   vec!(Vec2::new(0, 1), Vec2::new(8, 0), Vec2::new(1, 0)));
+        let config = GameConfig { wrap: Wrap::Both, ..config };
+        assert_eq!(snake_moves(corner, &config),
+                   vec!(Vec2::new(0, 8), Vec2::new(0, 1), Vec2::new(8, 0), Vec2::new(1, 0)));
+    }
+
+    #[test]
+    fn snake_wraps_instead_of_hitting_the_wall() {
+        for (wrap, state) in [(Wrap::Off, PlayState::GameOver(GameOverReason::Wall)),
+                              (Wrap::Horizontal, PlayState::Normal)] {
+            let config = GameConfig { wrap, ..GameConfig::default() };
+            let mut simulation = Simulation::new(config, 1);
+            simulation.fruits.clear();
+            simulation.snake.body = vec!(Vec2::new(0, 4), Vec2::new(1, 4), Vec2::new(2, 4));
+            simulation.snake.direction_queue = vec!(Direction::Left);
+            while simulation.snake.body[0] == Vec2::new(0, 4) &&
+                  simulation.state == PlayState::Normal {
+                simulation.step(&[]);
+            }
+            assert_eq!(simulation.state, state);
+            if state == PlayState::Normal {
+                assert_eq!(simulation.snake.body[0], Vec2::new(8, 4));
+            }
+        }
+    }
+
+    #[test]
+    fn a_snake_split_by_the_edge_lands_as_two_pieces() {
+        let config = GameConfig { wrap: Wrap::Horizontal, ..GameConfig::default() };
+        let mut simulation = Simulation::new(config, 1);
+        let cells = [(1, 2), (0, 2), (8, 2), (7, 2)];
+        assert_eq!(falling(&cells, &simulation.config).piece, vec!(0, 0, 1, 1));
+
+        simulation.map[(0, 6)] = block(1, 0);
+        land(&mut simulation, &cells);
+        assert!(simulation.map[(0, 5)].is_block());
+        assert!(simulation.map[(1, 5)].is_block());
+        assert!(simulation.map[(7, 8)].is_block());
+        assert!(simulation.map[(8, 8)].is_block());
+        assert!(simulation.map[(8, 5)].is_empty());
+    }
+
+    #[test]
+    fn fruits_move_down_with_cleared_rows_but_not_past_walls() {
+        for (wall, fallen) in [(false, 6), (true, 5)] {
+            let mut simulation = Simulation::new(GameConfig::default(), 1);
+            for x in 0..9 {
+                simulation.map[(x, 8)] = block(1, 0);
+            }
+            if wall {
+                simulation.map[(3, 6)] = Cell::Wall;
+            }
+            simulation.fruits = vec!(Fruit::new(Vec2::new(3, 5), FruitKind::Normal));
+            simulation.clear();
+            assert_eq!(simulation.score.lines, 1);
+            assert_eq!(simulation.fruits[0].pos, Vec2::new(3, fallen));
+        }
+    }
+
+    #[test]
+    fn fruits_the_blocks_land_on_are_moved() {
+        let mut simulation = Simulation::new(GameConfig::default(), 1);
+        simulation.fruits = vec!(Fruit::new(Vec2::new(0, 8), FruitKind::Normal));
+        land(&mut simulation, &[(0, 2)]);
+        assert!(simulation.map[(0, 8)].is_block());
+        assert_eq!(simulation.fruits.len(), 1);
+        assert_ne!(simulation.fruits[0].pos, Vec2::new(0, 8));
+        assert!(simulation.map[simulation.fruits[0].pos.into_tuple()].is_empty());
+    }
+}
@@ -0,0 +1,78 @@
+use std::collections::VecDeque;
+
+use array2d::Array2D;
+use fastrand::Rng;
+use vek::Vec2;
+
+use crate::config::{FruitSpawn, GameConfig};
+use crate::simulation::{neighbours, snake_moves, Cell};
+
+// Every free cell that can be reached from one of `starts` without passing
+// through the stack, with its distance from the nearest of them in moves.
+pub fn reachable_cells(map: &Array2D<Cell>, starts: &[Vec2<usize>],
+                       config: &GameConfig) -> Vec<(Vec2<usize>, usize)> {
+    let (width, height) = (config.map_width, config.map_height);
+    let mut distance = Array2D::filled_with(None, width, height);
+    let mut cells = Vec::new();
+    let mut queue = VecDeque::new();
+    for start in starts.iter() {
+        if map[start.into_tuple()].is_empty() && distance[start.into_tuple()].is_none() {
+            distance[start.into_tuple()] = Some(0);
+            queue.push_back(*start);
+        }
+    }
+    while let Some(cell) = queue.pop_front() {
+        let here = distance[cell.into_tuple()].unwrap();
+        cells.push((cell, here));
+        for next in snake_moves(cell, config) {
+            if map[next.into_tuple()].is_empty() && distance[next.into_tuple()].is_none() {
+                distance[next.into_tuple()] = Some(here + 1);
+                queue.push_back(next);
+            }
+        }
+    }
+    cells
+}
+
+// Chooses one of `candidates`, as returned by `reachable_cells`, the way
+// the configured strategy asks for.
+pub fn pick(candidates: &[(Vec2<usize>, usize)], map: &Array2D<Cell>, config: &GameConfig,
+            rng: &Rng) -> Option<Vec2<usize>> {
+    if candidates.is_empty() {
+        return None;
+    }
+    match config.fruit_spawn {
+        FruitSpawn::Uniform => Some(candidates[rng.usize(0..candidates.len())].0),
+        FruitSpawn::NearStack => {
+            // Cells touching the stack or the floor are five times as likely
+            // per touching side as cells out in the open.
+            let weights: Vec<usize> = candidates.iter()
+                .map(|(cell, _)| 1 + 4 * supports(*cell, map, config))
+                .collect();
+            let mut roll = rng.usize(0..weights.iter().sum::<usize>());
+            for (i, weight) in weights.iter().enumerate() {
+                if roll < *weight {
+                    return Some(candidates[i].0);
+                }
+                roll -= weight;
+            }
+            None
+        }
+        FruitSpawn::FarFromHead => {
+            let farthest = candidates.iter().map(|(_, distance)| *distance).max().unwrap();
+            let far: Vec<Vec2<usize>> = candidates.iter()
+                .filter(|(_, distance)| *distance * 4 >= farthest * 3)
+                .map(|(cell, _)| *cell)
+                .collect();
+            Some(far[rng.usize(0..far.len())])
+        }
+    }
+}
+
+// How many sides of `cell` are the stack or the floor.
+fn supports(cell: Vec2<usize>, map: &Array2D<Cell>, config: &GameConfig) -> usize {
+    let floor = if cell.y + 1 == config.map_height { 1 } else { 0 };
+    floor + neighbours(cell, config.map_width, config.map_height).iter()
+        .filter(|next| !map[next.into_tuple()].is_empty())
+        .count()
+}